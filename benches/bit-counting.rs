@@ -1,15 +1,33 @@
 use criterion::black_box;
+use parallel_bit_counting::divide_and_conquer_count_ones::multiply_parallel_count_ones_128_into;
+use parallel_bit_counting::divide_and_conquer_count_ones::multiply_parallel_count_ones_16_into;
+use parallel_bit_counting::divide_and_conquer_count_ones::multiply_parallel_count_ones_32_into;
+use parallel_bit_counting::divide_and_conquer_count_ones::multiply_parallel_count_ones_64_into;
 use parallel_bit_counting::divide_and_conquer_count_ones::naive_parallel_count_ones_128;
 use parallel_bit_counting::divide_and_conquer_count_ones::naive_parallel_count_ones_16;
 use parallel_bit_counting::divide_and_conquer_count_ones::naive_parallel_count_ones_32;
 use parallel_bit_counting::divide_and_conquer_count_ones::naive_parallel_count_ones_64;
+use parallel_bit_counting::harley_seal_count_ones::harley_seal_count_ones;
+use parallel_bit_counting::lookup_table_count_ones::lookup_16_count_ones_into;
+use parallel_bit_counting::lookup_table_count_ones::lookup_8_count_ones_into;
 use parallel_bit_counting::naive_count_ones::naive_count_bits_128;
 use parallel_bit_counting::naive_count_ones::naive_count_bits_16;
 use parallel_bit_counting::naive_count_ones::naive_count_bits_32;
 use parallel_bit_counting::naive_count_ones::naive_count_bits_64;
+use parallel_bit_counting::positional_count_ones::naive_positional_count_ones;
+use parallel_bit_counting::positional_count_ones::positional_count_ones;
 
-use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
-use parallel_bit_counting::{calculate_mask, count_ones::count_ones};
+use criterion::measurement::WallTime;
+use criterion::{
+    criterion_group, criterion_main, BenchmarkGroup, BenchmarkId, Criterion, Throughput,
+};
+use parallel_bit_counting::bit_counter::Registry;
+use parallel_bit_counting::count_ones::Word;
+use parallel_bit_counting::simd;
+use parallel_bit_counting::{
+    calculate_mask::GetMask,
+    count_ones::{count_ones_into, count_ones_total},
+};
 use rand::Rng;
 
 fn gen_random_input_u16(m: usize) -> Vec<u16> {
@@ -72,21 +90,82 @@ fn benchmark(c: &mut Criterion) {
                 },
             );
         }
+        {
+            let input = gen_random_input_u16(1 << m);
+            group.bench_with_input(
+                BenchmarkId::new("Multiply Divide-And-Conquer Bit Counting, 16-bit", 1 << m),
+                &input,
+                |b, x| {
+                    let mut out = vec![0; x.len()];
+                    b.iter(|| {
+                        multiply_parallel_count_ones_16_into(x, &mut out);
+                        black_box(&mut out);
+                    })
+                },
+            );
+        }
         {
             let input = gen_random_input_u16(1 << m);
             group.bench_with_input(
                 BenchmarkId::new("Parallel Bit Counting, 16-bit", 1 << m),
                 &input,
                 |b, x| {
+                    let mut out = vec![0; x.len()];
+                    b.iter(|| {
+                        count_ones_into(x, &u16::MASKS, &mut out);
+                        black_box(&mut out);
+                    })
+                },
+            );
+        }
+        {
+            let input = gen_random_input_u16(1 << m);
+            group.bench_with_input(
+                BenchmarkId::new("Total Parallel Bit Counting, 16-bit", 1 << m),
+                &input,
+                |b, x| {
+                    b.iter(|| {
+                        black_box(count_ones_total(x));
+                    })
+                },
+            );
+        }
+        {
+            let input = gen_random_input_u16(1 << m);
+            group.bench_with_input(
+                BenchmarkId::new("Harley-Seal Bit Counting, 16-bit", 1 << m),
+                &input,
+                |b, x| {
+                    b.iter(|| {
+                        black_box(harley_seal_count_ones(x));
+                    })
+                },
+            );
+        }
+        {
+            let input = gen_random_input_u16(1 << m);
+            group.bench_with_input(
+                BenchmarkId::new("8-bit Lookup Table Bit Counting, 16-bit", 1 << m),
+                &input,
+                |b, x| {
+                    let mut out = vec![0u8; x.len()];
+                    b.iter(|| {
+                        lookup_8_count_ones_into(x, &mut out);
+                        black_box(&mut out);
+                    })
+                },
+            );
+        }
+        {
+            let input = gen_random_input_u16(1 << m);
+            group.bench_with_input(
+                BenchmarkId::new("16-bit Lookup Table Bit Counting, 16-bit", 1 << m),
+                &input,
+                |b, x| {
+                    let mut out = vec![0u8; x.len()];
                     b.iter(|| {
-                        let masks = black_box(calculate_mask::get_mask(
-                            calculate_mask::get_basic_masks_u16(),
-                        ));
-                        black_box(count_ones(
-                            x,
-                            masks,
-                            parallel_bit_counting::count_ones::WordLength::U16,
-                        ));
+                        lookup_16_count_ones_into(x, &mut out);
+                        black_box(&mut out);
                     })
                 },
             );
@@ -129,21 +208,82 @@ fn benchmark(c: &mut Criterion) {
                 },
             );
         }
+        {
+            let input = gen_random_input_u32(1 << m);
+            group.bench_with_input(
+                BenchmarkId::new("Multiply Divide-And-Conquer Bit Counting, 32-bit", 1 << m),
+                &input,
+                |b, x| {
+                    let mut out = vec![0; x.len()];
+                    b.iter(|| {
+                        multiply_parallel_count_ones_32_into(x, &mut out);
+                        black_box(&mut out);
+                    })
+                },
+            );
+        }
         {
             let input = gen_random_input_u32(1 << m);
             group.bench_with_input(
                 BenchmarkId::new("Parallel Bit Counting, 32-bit", 1 << m),
                 &input,
+                |b, x| {
+                    let mut out = vec![0; x.len()];
+                    b.iter(|| {
+                        count_ones_into(x, &u32::MASKS, &mut out);
+                        black_box(&mut out);
+                    })
+                },
+            );
+        }
+        {
+            let input = gen_random_input_u32(1 << m);
+            group.bench_with_input(
+                BenchmarkId::new("Total Parallel Bit Counting, 32-bit", 1 << m),
+                &input,
                 |b, x| {
                     b.iter(|| {
-                        let masks = black_box(calculate_mask::get_mask(
-                            calculate_mask::get_basic_masks_u32(),
-                        ));
-                        black_box(count_ones(
-                            x,
-                            masks,
-                            parallel_bit_counting::count_ones::WordLength::U32,
-                        ));
+                        black_box(count_ones_total(x));
+                    })
+                },
+            );
+        }
+        {
+            let input = gen_random_input_u32(1 << m);
+            group.bench_with_input(
+                BenchmarkId::new("Harley-Seal Bit Counting, 32-bit", 1 << m),
+                &input,
+                |b, x| {
+                    b.iter(|| {
+                        black_box(harley_seal_count_ones(x));
+                    })
+                },
+            );
+        }
+        {
+            let input = gen_random_input_u32(1 << m);
+            group.bench_with_input(
+                BenchmarkId::new("8-bit Lookup Table Bit Counting, 32-bit", 1 << m),
+                &input,
+                |b, x| {
+                    let mut out = vec![0u8; x.len()];
+                    b.iter(|| {
+                        lookup_8_count_ones_into(x, &mut out);
+                        black_box(&mut out);
+                    })
+                },
+            );
+        }
+        {
+            let input = gen_random_input_u32(1 << m);
+            group.bench_with_input(
+                BenchmarkId::new("16-bit Lookup Table Bit Counting, 32-bit", 1 << m),
+                &input,
+                |b, x| {
+                    let mut out = vec![0u8; x.len()];
+                    b.iter(|| {
+                        lookup_16_count_ones_into(x, &mut out);
+                        black_box(&mut out);
                     })
                 },
             );
@@ -186,21 +326,96 @@ fn benchmark(c: &mut Criterion) {
                 },
             );
         }
+        {
+            let input = gen_random_input_u64(1 << m);
+            group.bench_with_input(
+                BenchmarkId::new("Multiply Divide-And-Conquer Bit Counting, 64-bit", 1 << m),
+                &input,
+                |b, x| {
+                    let mut out = vec![0; x.len()];
+                    b.iter(|| {
+                        multiply_parallel_count_ones_64_into(x, &mut out);
+                        black_box(&mut out);
+                    })
+                },
+            );
+        }
         {
             let input = gen_random_input_u64(1 << m);
             group.bench_with_input(
                 BenchmarkId::new("Parallel Bit Counting, 64-bit", 1 << m),
                 &input,
                 |b, x| {
+                    let mut out = vec![0; x.len()];
                     b.iter(|| {
-                        let masks = black_box(calculate_mask::get_mask(
-                            calculate_mask::get_basic_masks_u64(),
-                        ));
-                        black_box(count_ones(
-                            x,
-                            masks,
-                            parallel_bit_counting::count_ones::WordLength::U64,
-                        ));
+                        count_ones_into(x, &u64::MASKS, &mut out);
+                        black_box(&mut out);
+                    })
+                },
+            );
+        }
+        {
+            let input = gen_random_input_u64(1 << m);
+            group.bench_with_input(
+                BenchmarkId::new("SIMD Parallel Bit Counting, 64-bit", 1 << m),
+                &input,
+                |b, x| {
+                    let mut out = vec![0; x.len()];
+                    b.iter(|| {
+                        simd::count_ones_into(x, &mut out);
+                        black_box(&mut out);
+                    })
+                },
+            );
+        }
+        {
+            let input = gen_random_input_u64(1 << m);
+            group.bench_with_input(
+                BenchmarkId::new("Total Parallel Bit Counting, 64-bit", 1 << m),
+                &input,
+                |b, x| {
+                    b.iter(|| {
+                        black_box(count_ones_total(x));
+                    })
+                },
+            );
+        }
+        {
+            let input = gen_random_input_u64(1 << m);
+            group.bench_with_input(
+                BenchmarkId::new("Harley-Seal Bit Counting, 64-bit", 1 << m),
+                &input,
+                |b, x| {
+                    b.iter(|| {
+                        black_box(harley_seal_count_ones(x));
+                    })
+                },
+            );
+        }
+        {
+            let input = gen_random_input_u64(1 << m);
+            group.bench_with_input(
+                BenchmarkId::new("8-bit Lookup Table Bit Counting, 64-bit", 1 << m),
+                &input,
+                |b, x| {
+                    let mut out = vec![0u8; x.len()];
+                    b.iter(|| {
+                        lookup_8_count_ones_into(x, &mut out);
+                        black_box(&mut out);
+                    })
+                },
+            );
+        }
+        {
+            let input = gen_random_input_u64(1 << m);
+            group.bench_with_input(
+                BenchmarkId::new("16-bit Lookup Table Bit Counting, 64-bit", 1 << m),
+                &input,
+                |b, x| {
+                    let mut out = vec![0u8; x.len()];
+                    b.iter(|| {
+                        lookup_16_count_ones_into(x, &mut out);
+                        black_box(&mut out);
                     })
                 },
             );
@@ -243,21 +458,82 @@ fn benchmark(c: &mut Criterion) {
                 },
             );
         }
+        {
+            let input = gen_random_input_u128(1 << m);
+            group.bench_with_input(
+                BenchmarkId::new("Multiply Divide-And-Conquer Bit Counting, 128-bit", 1 << m),
+                &input,
+                |b, x| {
+                    let mut out = vec![0; x.len()];
+                    b.iter(|| {
+                        multiply_parallel_count_ones_128_into(x, &mut out);
+                        black_box(&mut out);
+                    })
+                },
+            );
+        }
         {
             let input = gen_random_input_u128(1 << m);
             group.bench_with_input(
                 BenchmarkId::new("Parallel Bit Counting, 128-bit", 1 << m),
                 &input,
+                |b, x| {
+                    let mut out = vec![0; x.len()];
+                    b.iter(|| {
+                        count_ones_into(x, &u128::MASKS, &mut out);
+                        black_box(&mut out);
+                    })
+                },
+            );
+        }
+        {
+            let input = gen_random_input_u128(1 << m);
+            group.bench_with_input(
+                BenchmarkId::new("Total Parallel Bit Counting, 128-bit", 1 << m),
+                &input,
                 |b, x| {
                     b.iter(|| {
-                        let masks = black_box(calculate_mask::get_mask(
-                            calculate_mask::get_basic_masks_u128(),
-                        ));
-                        black_box(count_ones(
-                            x,
-                            masks,
-                            parallel_bit_counting::count_ones::WordLength::U128,
-                        ));
+                        black_box(count_ones_total(x));
+                    })
+                },
+            );
+        }
+        {
+            let input = gen_random_input_u128(1 << m);
+            group.bench_with_input(
+                BenchmarkId::new("Harley-Seal Bit Counting, 128-bit", 1 << m),
+                &input,
+                |b, x| {
+                    b.iter(|| {
+                        black_box(harley_seal_count_ones(x));
+                    })
+                },
+            );
+        }
+        {
+            let input = gen_random_input_u128(1 << m);
+            group.bench_with_input(
+                BenchmarkId::new("8-bit Lookup Table Bit Counting, 128-bit", 1 << m),
+                &input,
+                |b, x| {
+                    let mut out = vec![0u8; x.len()];
+                    b.iter(|| {
+                        lookup_8_count_ones_into(x, &mut out);
+                        black_box(&mut out);
+                    })
+                },
+            );
+        }
+        {
+            let input = gen_random_input_u128(1 << m);
+            group.bench_with_input(
+                BenchmarkId::new("16-bit Lookup Table Bit Counting, 128-bit", 1 << m),
+                &input,
+                |b, x| {
+                    let mut out = vec![0u8; x.len()];
+                    b.iter(|| {
+                        lookup_16_count_ones_into(x, &mut out);
+                        black_box(&mut out);
                     })
                 },
             );
@@ -278,5 +554,51 @@ fn benchmark(c: &mut Criterion) {
         }
     }
 }
-criterion_group!(benches, benchmark);
+fn bench_counters<T: Registry>(group: &mut BenchmarkGroup<WallTime>, bits: usize, input: &[T]) {
+    for counter in T::counters() {
+        group.bench_with_input(
+            BenchmarkId::new(format!("{}, {}-bit", counter.name(), bits), input.len()),
+            input,
+            |b, x| {
+                let mut out = vec![0; x.len()];
+                b.iter(|| {
+                    counter.count(x, &mut out);
+                    black_box(&mut out);
+                })
+            },
+        );
+    }
+}
+fn benchmark_registry(c: &mut Criterion) {
+    let mut group = c.benchmark_group("Registered Bit Counters");
+    for m in 4..20 {
+        group.throughput(Throughput::Elements(1 << m as u64));
+        bench_counters(&mut group, 16, &gen_random_input_u16(1 << m));
+        bench_counters(&mut group, 32, &gen_random_input_u32(1 << m));
+        bench_counters(&mut group, 64, &gen_random_input_u64(1 << m));
+        bench_counters(&mut group, 128, &gen_random_input_u128(1 << m));
+    }
+}
+fn bench_positional<T: Word, const BITS: usize>(group: &mut BenchmarkGroup<WallTime>, input: &[T]) {
+    group.bench_with_input(
+        BenchmarkId::new(format!("Carry-save, {}-bit", BITS), input.len()),
+        input,
+        |b, x| b.iter(|| black_box(positional_count_ones::<T, BITS>(x))),
+    );
+    group.bench_with_input(
+        BenchmarkId::new(format!("Naïve per-bit, {}-bit", BITS), input.len()),
+        input,
+        |b, x| b.iter(|| black_box(naive_positional_count_ones::<T, BITS>(x))),
+    );
+}
+fn benchmark_positional(c: &mut Criterion) {
+    let mut group = c.benchmark_group("Positional Popcount");
+    for m in 4..20 {
+        group.throughput(Throughput::Elements(1 << m as u64));
+        bench_positional::<_, 16>(&mut group, &gen_random_input_u16(1 << m));
+        bench_positional::<_, 32>(&mut group, &gen_random_input_u32(1 << m));
+        bench_positional::<_, 64>(&mut group, &gen_random_input_u64(1 << m));
+    }
+}
+criterion_group!(benches, benchmark, benchmark_registry, benchmark_positional);
 criterion_main!(benches);
@@ -0,0 +1,141 @@
+use crate::calculate_mask::GetMask;
+use crate::count_ones::combine_factor;
+use crate::count_ones::count_ones_into;
+use crate::count_ones::PackedSum;
+use crate::count_ones::Word;
+use crate::kernighan_count_ones::kernighan_count_ones_into;
+use crate::kernighan_count_ones::kernighan_count_ones_total;
+use crate::kernighan_count_ones::kernighan_count_ones_word;
+
+/// How [`adaptive_count_ones_into`] tells sparse blocks from dense ones.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DensityConfig {
+    /// The number of words dispatched together. It is rounded up to a multiple of the combine
+    /// factor, so a dense block is packed as it would have been on its own
+    pub block_len: usize,
+    /// The number of evenly spaced words of a block whose ones are sampled
+    pub sample_len: usize,
+    /// Blocks whose sampled words have at most this many ones per word are counted with
+    /// Kernighan's loop, and the others with `count_ones`
+    pub sparse_ones_per_word: usize,
+}
+
+impl Default for DensityConfig {
+    fn default() -> Self {
+        DensityConfig {
+            block_len: 1 << 10,
+            sample_len: 16,
+            // Kernighan's loop breaks even with count_ones at about 4 ones per u64
+            sparse_ones_per_word: 3,
+        }
+    }
+}
+
+impl DensityConfig {
+    fn block_len<T: Word>(&self) -> usize {
+        self.block_len
+            .next_multiple_of(combine_factor::<T>())
+            .max(combine_factor::<T>())
+    }
+}
+
+/// Samples the density of `block`. The sampling stops at the first word that takes the sampled
+/// words over the ones of a sparse block, so it costs at most one dense word more than counting a
+/// sparse sample.
+fn is_sparse<T: Word>(block: &[T], config: &DensityConfig) -> bool {
+    let step = (block.len() / config.sample_len.max(1)).max(1);
+    let sampled = block.len().div_ceil(step).min(config.sample_len);
+    let budget = (config.sparse_ones_per_word * sampled) as u64;
+    let mut ones = 0;
+    for word in block.iter().step_by(step).take(sampled) {
+        ones += kernighan_count_ones_word(*word);
+        if ones > budget {
+            return false;
+        }
+    }
+    true
+}
+
+/// Counts the ones of every word in `experiment` into `out`, like [`count_ones_into`], but counts
+/// the blocks that are sampled to be sparse with Kernighan's loop, which is faster for words with
+/// few ones.
+pub fn adaptive_count_ones_into<T: Word + GetMask>(
+    experiment: &[T],
+    out: &mut [T],
+    config: &DensityConfig,
+) {
+    assert_eq!(
+        experiment.len(),
+        out.len(),
+        "the output buffer must be as long as the input"
+    );
+    let block_len = config.block_len::<T>();
+    for (block, out) in experiment.chunks(block_len).zip(out.chunks_mut(block_len)) {
+        if is_sparse(block, config) {
+            kernighan_count_ones_into(block, out);
+        } else {
+            count_ones_into(block, &T::MASKS, out);
+        }
+    }
+}
+
+/// Counts the ones of every word in `experiment`, like [`adaptive_count_ones_into`].
+pub fn adaptive_count_ones<T: Word + GetMask>(experiment: &[T], config: &DensityConfig) -> Vec<T> {
+    let mut acc = vec![T::zero(); experiment.len()];
+    adaptive_count_ones_into(experiment, &mut acc, config);
+    acc
+}
+
+/// Counts the total number of ones in `experiment`, with Kernighan's loop for the sparse blocks,
+/// and by adding up the packed words of the dense blocks.
+pub fn adaptive_count_ones_total<T: Word + GetMask>(
+    experiment: &[T],
+    config: &DensityConfig,
+) -> u64 {
+    let mut sum = PackedSum::new();
+    let mut sparse_total = 0;
+    for block in experiment.chunks(config.block_len::<T>()) {
+        if is_sparse(block, config) {
+            sparse_total += kernighan_count_ones_total(block);
+        } else {
+            sum.add(block);
+        }
+    }
+    sparse_total + sum.total()
+}
+
+#[test]
+fn test_mixed_density() {
+    use crate::count_ones::count_ones_total;
+    use rand::Rng;
+    let mut rng = rand::thread_rng();
+    // Alternating runs of sparse and dense words
+    let val: Vec<u64> = (0..20_000)
+        .map(|i| {
+            if (i / 3000) % 2 == 0 {
+                rng.gen::<u64>() & rng.gen::<u64>() & rng.gen::<u64>() & rng.gen::<u64>()
+            } else {
+                rng.gen::<u64>()
+            }
+        })
+        .collect();
+    let mut expected = vec![0; val.len()];
+    count_ones_into(&val, &u64::MASKS, &mut expected);
+    let expected_total = count_ones_total(&val);
+    for &block_len in &[0, 1, 7, 100, 1 << 10, 1 << 20] {
+        for &sparse_ones_per_word in &[0, 4, 64] {
+            let config = DensityConfig {
+                block_len,
+                sparse_ones_per_word,
+                ..DensityConfig::default()
+            };
+            assert_eq!(adaptive_count_ones(&val, &config), expected, "{:?}", config);
+            assert_eq!(
+                adaptive_count_ones_total(&val, &config),
+                expected_total,
+                "{:?}",
+                config
+            );
+        }
+    }
+}
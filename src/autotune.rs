@@ -0,0 +1,450 @@
+use crate::bit_counter::counter_by_name;
+use crate::bit_counter::BitCounter;
+use crate::bit_counter::Registry;
+use crate::count_ones::Word;
+use core::fmt;
+use std::fs::File;
+use std::io;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::BufWriter;
+use std::io::Write;
+use std::ops::Range;
+use std::path::Path;
+use std::time::Duration;
+use std::time::Instant;
+
+/// The counter a [`Profile`] falls back to for widths and sizes it has not been tuned for
+const FALLBACK: &str = "packed";
+
+/// Returns whether the CPU has a popcount instruction.
+pub fn cpu_has_popcnt() -> bool {
+    #[cfg(target_arch = "x86_64")]
+    {
+        is_x86_feature_detected!("popcnt")
+    }
+    #[cfg(target_arch = "aarch64")]
+    {
+        // CNT is part of every AArch64 CPU
+        true
+    }
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+    {
+        false
+    }
+}
+
+/// Returns whether the builtin `count_ones` compiles to the popcount instruction. On x86_64 this
+/// takes `-C target-feature=+popcnt`, even if the CPU has the instruction; otherwise the compiler
+/// emits a SWAR sequence much like the divide-and-conquer algorithm.
+pub fn builtin_uses_popcnt() -> bool {
+    cfg!(any(target_feature = "popcnt", target_arch = "aarch64"))
+}
+
+/// What [`tune`] measures.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TuneConfig {
+    /// The input sizes, as log_2 of the number of words
+    pub log_lens: Range<usize>,
+    /// The number of times every counter is timed on every input. The fastest time is kept
+    pub rounds: usize,
+}
+
+impl Default for TuneConfig {
+    fn default() -> Self {
+        TuneConfig {
+            log_lens: 4..20,
+            rounds: 3,
+        }
+    }
+}
+
+/// The fastest counters for inputs of 2^log_len words
+struct Choice<T> {
+    log_len: usize,
+    count: Box<dyn BitCounter<T>>,
+    total: Box<dyn BitCounter<T>>,
+}
+
+/// The fastest counters of one word width, resolved from their names once, when the profile is
+/// tuned or loaded.
+pub struct TunedCounters<T> {
+    choices: Vec<Choice<T>>,
+    /// The counter used while the width has not been tuned
+    fallback: Box<dyn BitCounter<T>>,
+}
+
+impl<T: Registry> TunedCounters<T> {
+    fn new() -> Self {
+        TunedCounters {
+            choices: Vec::new(),
+            fallback: resolve(FALLBACK),
+        }
+    }
+
+    /// Returns the choice for the tuned size nearest to `len` words.
+    fn nearest(&self, len: usize) -> Option<&Choice<T>> {
+        nearest_by_log_len(&self.choices, len, |choice| choice.log_len)
+    }
+}
+
+/// Returns the item of `items` tuned for the size nearest to `len` words, where `log_len` gives
+/// the size of an item as log_2 of its number of words.
+fn nearest_by_log_len<I, F>(items: I, len: usize, log_len: F) -> Option<I::Item>
+where
+    I: IntoIterator,
+    F: Fn(&I::Item) -> usize,
+{
+    let len_log = (usize::BITS - len.max(1).leading_zeros() - 1) as isize;
+    items
+        .into_iter()
+        .min_by_key(|item| (log_len(item) as isize - len_log).abs())
+}
+
+/// Returns the registered counter called `name`, or the fallback counter if there is none.
+fn resolve<T: Registry>(name: &str) -> Box<dyn BitCounter<T>> {
+    counter_by_name::<T>(name)
+        .or_else(|| counter_by_name::<T>(FALLBACK))
+        .expect("the fallback counter is registered for every word")
+}
+
+/// The word widths a [`Profile`] is tuned for.
+pub trait Tuned: Registry + Word {
+    fn tuned(profile: &Profile) -> &TunedCounters<Self>;
+    fn tuned_mut(profile: &mut Profile) -> &mut TunedCounters<Self>;
+}
+
+macro_rules! tuned {
+    ($t:ty, $field:ident) => {
+        impl Tuned for $t {
+            fn tuned(profile: &Profile) -> &TunedCounters<$t> {
+                &profile.$field
+            }
+            fn tuned_mut(profile: &mut Profile) -> &mut TunedCounters<$t> {
+                &mut profile.$field
+            }
+        }
+    };
+}
+
+tuned!(u16, counters_16);
+tuned!(u32, counters_32);
+tuned!(u64, counters_64);
+tuned!(u128, counters_128);
+
+/// The fastest counters for every word width and input size on a machine, timed separately for
+/// counting every word and for counting the total.
+pub struct Profile {
+    /// Whether the CPU had a popcount instruction when the profile was tuned
+    pub cpu_has_popcnt: bool,
+    /// Whether the builtin compiled to the popcount instruction when the profile was tuned
+    pub builtin_uses_popcnt: bool,
+    counters_16: TunedCounters<u16>,
+    counters_32: TunedCounters<u32>,
+    counters_64: TunedCounters<u64>,
+    counters_128: TunedCounters<u128>,
+}
+
+/// The bits of the word, log_2 of the number of words, and the names of the fastest counters for
+/// `count` and for `total`
+type Entry = (usize, usize, &'static str, &'static str);
+
+fn entries_of<T: Word>(counters: &TunedCounters<T>) -> impl Iterator<Item = Entry> + '_ {
+    counters.choices.iter().map(|choice| {
+        let count = choice.count.name();
+        (T::BITS, choice.log_len, count, choice.total.name())
+    })
+}
+
+impl Profile {
+    fn new(cpu_has_popcnt: bool, builtin_uses_popcnt: bool) -> Self {
+        Profile {
+            cpu_has_popcnt,
+            builtin_uses_popcnt,
+            counters_16: TunedCounters::new(),
+            counters_32: TunedCounters::new(),
+            counters_64: TunedCounters::new(),
+            counters_128: TunedCounters::new(),
+        }
+    }
+
+    /// Resolves the counters called `count` and `total`, and adds them for inputs of 2^log_len
+    /// words of `bits` bits. Returns `false` if `bits` is not a tuned width.
+    fn push(&mut self, bits: usize, log_len: usize, count: &str, total: &str) -> bool {
+        fn push<T: Tuned>(profile: &mut Profile, log_len: usize, count: &str, total: &str) {
+            T::tuned_mut(profile).choices.push(Choice {
+                log_len,
+                count: resolve(count),
+                total: resolve(total),
+            });
+        }
+        match bits {
+            16 => push::<u16>(self, log_len, count, total),
+            32 => push::<u32>(self, log_len, count, total),
+            64 => push::<u64>(self, log_len, count, total),
+            128 => push::<u128>(self, log_len, count, total),
+            _ => return false,
+        }
+        true
+    }
+
+    /// Returns the choices of every width, in the order they are written.
+    fn entries(&self) -> impl Iterator<Item = Entry> + '_ {
+        entries_of(&self.counters_16)
+            .chain(entries_of(&self.counters_32))
+            .chain(entries_of(&self.counters_64))
+            .chain(entries_of(&self.counters_128))
+    }
+
+    /// Returns the entry of the tuned size nearest to `len` words of `bits` bits.
+    fn nearest_entry(&self, bits: usize, len: usize) -> Option<Entry> {
+        let entries = self.entries().filter(|(b, _, _, _)| *b == bits);
+        nearest_by_log_len(entries, len, |(_, log_len, _, _)| *log_len)
+    }
+
+    /// Returns the name of the fastest counter for counting every one of `len` words of `bits`
+    /// bits, if `bits` has been tuned. Sizes outside of the tuned range use the nearest tuned size.
+    pub fn choice(&self, bits: usize, len: usize) -> Option<&'static str> {
+        self.nearest_entry(bits, len).map(|(_, _, count, _)| count)
+    }
+
+    /// Like [`Profile::choice`], but for counting the total number of ones.
+    pub fn total_choice(&self, bits: usize, len: usize) -> Option<&'static str> {
+        self.nearest_entry(bits, len).map(|(_, _, _, total)| total)
+    }
+
+    /// Returns the fastest counter for counting every one of `len` words of type `T`.
+    pub fn counter<T: Tuned>(&self, len: usize) -> &dyn BitCounter<T> {
+        let tuned = T::tuned(self);
+        tuned
+            .nearest(len)
+            .map_or(tuned.fallback.as_ref(), |choice| choice.count.as_ref())
+    }
+
+    /// Returns the fastest counter for counting the total number of ones in `len` words of type
+    /// `T`.
+    pub fn total_counter<T: Tuned>(&self, len: usize) -> &dyn BitCounter<T> {
+        let tuned = T::tuned(self);
+        tuned
+            .nearest(len)
+            .map_or(tuned.fallback.as_ref(), |choice| choice.total.as_ref())
+    }
+
+    /// Counts the ones of every word in `experiment` into `out` with the fastest counter.
+    pub fn count<T: Tuned>(&self, experiment: &[T], out: &mut [u8]) {
+        self.counter::<T>(experiment.len()).count(experiment, out)
+    }
+
+    /// Counts the total number of ones in `experiment` with the fastest counter for totals.
+    pub fn total<T: Tuned>(&self, experiment: &[T]) -> u64 {
+        self.total_counter::<T>(experiment.len()).total(experiment)
+    }
+
+    /// Returns whether the profile was tuned on a machine and build with the same popcount
+    /// support as this one.
+    pub fn matches_host(&self) -> bool {
+        self.cpu_has_popcnt == cpu_has_popcnt() && self.builtin_uses_popcnt == builtin_uses_popcnt()
+    }
+
+    /// Writes the profile as text, with a line per width and size.
+    pub fn write_to<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writeln!(writer, "cpu_has_popcnt {}", self.cpu_has_popcnt)?;
+        writeln!(writer, "builtin_uses_popcnt {}", self.builtin_uses_popcnt)?;
+        for (bits, log_len, count, total) in self.entries() {
+            writeln!(writer, "{} {} {} {}", bits, log_len, count, total)?;
+        }
+        Ok(())
+    }
+
+    /// Reads a profile written by [`Profile::write_to`], and resolves its counters. A line with a
+    /// single counter uses it for both counting and totals, and an unknown counter falls back to
+    /// the packed one.
+    pub fn read_from<R: BufRead>(reader: R) -> io::Result<Profile> {
+        let invalid = |line: &str| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid profile line: {}", line),
+            )
+        };
+        let mut lines = reader.lines();
+        let mut flag = |name: &str| -> io::Result<bool> {
+            let line = lines.next().unwrap_or_else(|| Ok(String::new()))?;
+            match line.split_once(' ') {
+                Some((key, value)) if key == name => value.parse().map_err(|_| invalid(&line)),
+                _ => Err(invalid(&line)),
+            }
+        };
+        let cpu_has_popcnt = flag("cpu_has_popcnt")?;
+        let builtin_uses_popcnt = flag("builtin_uses_popcnt")?;
+        let mut profile = Profile::new(cpu_has_popcnt, builtin_uses_popcnt);
+        for line in lines {
+            let line = line?;
+            let fields: Vec<&str> = line.split(' ').collect();
+            let (bits, log_len, count, total) = match fields[..] {
+                [bits, log_len, count] => (bits, log_len, count, count),
+                [bits, log_len, count, total] => (bits, log_len, count, total),
+                _ => return Err(invalid(&line)),
+            };
+            let bits = bits.parse().map_err(|_| invalid(&line))?;
+            let log_len = log_len.parse().map_err(|_| invalid(&line))?;
+            if !profile.push(bits, log_len, count, total) {
+                return Err(invalid(&line));
+            }
+        }
+        Ok(profile)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_to(&mut writer)?;
+        writer.flush()
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Profile> {
+        Profile::read_from(BufReader::new(File::open(path)?))
+    }
+}
+
+impl PartialEq for Profile {
+    fn eq(&self, other: &Profile) -> bool {
+        self.cpu_has_popcnt == other.cpu_has_popcnt
+            && self.builtin_uses_popcnt == other.builtin_uses_popcnt
+            && self.entries().eq(other.entries())
+    }
+}
+
+impl Eq for Profile {}
+
+impl fmt::Debug for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Profile")
+            .field("cpu_has_popcnt", &self.cpu_has_popcnt)
+            .field("builtin_uses_popcnt", &self.builtin_uses_popcnt)
+            .field("choices", &self.entries().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+/// Returns the fastest of `rounds` runs of `f`.
+fn fastest<F: FnMut()>(rounds: usize, mut f: F) -> Duration {
+    (0..rounds.max(1))
+        .map(|_| {
+            let start = Instant::now();
+            f();
+            start.elapsed()
+        })
+        .min()
+        .unwrap()
+}
+
+/// Times `count` and `total` of every counter of `T` on random inputs of every size, and adds
+/// the fastest of each to `profile`.
+fn tune_width<T: Tuned>(config: &TuneConfig, profile: &mut Profile) {
+    use rand::Rng;
+    let mut rng = rand::thread_rng();
+    let counters = T::counters();
+    for log_len in config.log_lens.clone() {
+        let mut bytes = vec![0; T::BITS / 8];
+        let experiment: Vec<T> = (0..1 << log_len)
+            .map(|_| {
+                rng.fill(&mut bytes[..]);
+                T::from_le_bytes(&bytes)
+            })
+            .collect();
+        let mut out = vec![0; experiment.len()];
+        let count = counters
+            .iter()
+            .min_by_key(|counter| {
+                fastest(config.rounds, || {
+                    counter.count(&experiment, &mut out);
+                    std::hint::black_box(&mut out);
+                })
+            })
+            .expect("every word has registered counters");
+        let total = counters
+            .iter()
+            .min_by_key(|counter| {
+                fastest(config.rounds, || {
+                    std::hint::black_box(counter.total(&experiment));
+                })
+            })
+            .expect("every word has registered counters");
+        profile.push(T::BITS, log_len, count.name(), total.name());
+    }
+}
+
+/// Micro-benchmarks every registered counter on this machine, for every word width and size in
+/// `config`, and returns the fastest ones as a profile.
+pub fn tune(config: &TuneConfig) -> Profile {
+    let mut profile = Profile::new(cpu_has_popcnt(), builtin_uses_popcnt());
+    tune_width::<u16>(config, &mut profile);
+    tune_width::<u32>(config, &mut profile);
+    tune_width::<u64>(config, &mut profile);
+    tune_width::<u128>(config, &mut profile);
+    profile
+}
+
+#[test]
+fn test_tune_save_and_dispatch() {
+    use rand::Rng;
+    let config = TuneConfig {
+        log_lens: 4..7,
+        rounds: 1,
+    };
+    let profile = tune(&config);
+    assert!(profile.matches_host());
+    for &bits in &[16, 32, 64, 128] {
+        for len in 0..1 << 8 {
+            assert!(profile.choice(bits, len).is_some());
+            assert!(profile.total_choice(bits, len).is_some());
+        }
+    }
+    assert_eq!(profile.choice(8, 100), None);
+
+    let path = std::env::temp_dir().join(format!("popcount-profile-{}", std::process::id()));
+    profile.save(&path).unwrap();
+    let loaded = Profile::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(loaded, profile);
+
+    let mut rng = rand::thread_rng();
+    let val: Vec<u64> = (0..1000).map(|_| rng.gen()).collect();
+    let expected: Vec<u8> = val.iter().map(|x| x.count_ones() as u8).collect();
+    let mut out = vec![0; val.len()];
+    loaded.count(&val, &mut out);
+    assert_eq!(out, expected);
+    assert_eq!(
+        loaded.total(&val),
+        expected.iter().map(|&count| count as u64).sum::<u64>()
+    );
+}
+
+#[test]
+fn test_invalid_profile() {
+    let read = |text: &str| Profile::read_from(text.as_bytes());
+    assert!(read("cpu_has_popcnt true\nbuiltin_uses_popcnt false\n64 4 packed\n").is_ok());
+    assert!(read("").is_err());
+    assert!(read("cpu_has_popcnt maybe\nbuiltin_uses_popcnt false\n").is_err());
+    assert!(read("cpu_has_popcnt true\nbuiltin_uses_popcnt false\n64 packed\n").is_err());
+    assert!(read("cpu_has_popcnt true\nbuiltin_uses_popcnt false\n8 4 packed\n").is_err());
+    // An unknown counter falls back to the packed one
+    let profile = read("cpu_has_popcnt true\nbuiltin_uses_popcnt false\n64 4 unknown\n").unwrap();
+    assert_eq!(profile.counter::<u64>(16).name(), FALLBACK);
+    assert_eq!(profile.total_counter::<u64>(16).name(), FALLBACK);
+    // Untuned widths use the fallback too
+    assert_eq!(profile.counter::<u16>(16).name(), FALLBACK);
+    assert_eq!(profile.choice(16, 16), None);
+}
+
+#[test]
+fn test_separate_total_choice() {
+    let text = "cpu_has_popcnt true\nbuiltin_uses_popcnt false\n64 4 builtin harley-seal\n";
+    let profile = Profile::read_from(text.as_bytes()).unwrap();
+    assert_eq!(profile.choice(64, 16), Some("builtin"));
+    assert_eq!(profile.total_choice(64, 16), Some("harley-seal"));
+    assert_eq!(profile.counter::<u64>(16).name(), "builtin");
+    assert_eq!(profile.total_counter::<u64>(16).name(), "harley-seal");
+    let mut written = Vec::new();
+    profile.write_to(&mut written).unwrap();
+    assert_eq!(String::from_utf8(written).unwrap(), text);
+}
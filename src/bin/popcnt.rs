@@ -0,0 +1,402 @@
+use parallel_bit_counting::bit_counter::counter_by_name;
+use parallel_bit_counting::bit_counter::BitCounter;
+use parallel_bit_counting::bit_counter::Registry;
+use parallel_bit_counting::count_ones::Word;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::io::Write;
+use std::process;
+
+/// The number of bytes read at a time
+const READ_BUFFER_LEN: usize = 1 << 16;
+
+const USAGE: &str = "Usage: popcnt [OPTIONS] [FILE]...
+
+Counts the set bits of the concatenated FILEs, or of stdin if no FILE is given or FILE is -.
+The input is read as little-endian words, and a trailing incomplete word is padded with zeros.
+
+Options:
+  -w, --width <16|32|64|128>            word width [default: 64]
+  -a, --algorithm <ALGORITHM>           the name of a counter in the registry [default: packed]
+  -o, --output <total|words|histogram>  what to print [default: total]
+  -f, --format <plain|csv|json>         output format [default: plain]
+  -h, --help                            print this help";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Output {
+    /// The total number of set bits
+    Total,
+    /// The number of set bits of every word
+    Words,
+    /// The number of words with every possible number of set bits
+    Histogram,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Format {
+    Plain,
+    Csv,
+    Json,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Options {
+    width: usize,
+    /// The name of a registered `BitCounter`
+    algorithm: String,
+    output: Output,
+    format: Format,
+    files: Vec<String>,
+}
+
+impl Options {
+    /// Parses the arguments after the program name. Returns `Ok(None)` if help was asked for.
+    fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Option<Options>, String> {
+        let mut options = Options {
+            width: 64,
+            algorithm: String::from("packed"),
+            output: Output::Total,
+            format: Format::Plain,
+            files: Vec::new(),
+        };
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or_else(|| format!("{} needs a value", arg));
+            match arg.as_str() {
+                "-h" | "--help" => return Ok(None),
+                "-w" | "--width" => {
+                    options.width = match value()?.as_str() {
+                        "16" => 16,
+                        "32" => 32,
+                        "64" => 64,
+                        "128" => 128,
+                        other => return Err(format!("unsupported word width: {}", other)),
+                    }
+                }
+                "-a" | "--algorithm" => options.algorithm = value()?,
+                "-o" | "--output" => {
+                    options.output = match value()?.as_str() {
+                        "total" => Output::Total,
+                        "words" => Output::Words,
+                        "histogram" => Output::Histogram,
+                        other => return Err(format!("unknown output: {}", other)),
+                    }
+                }
+                "-f" | "--format" => {
+                    options.format = match value()?.as_str() {
+                        "plain" => Format::Plain,
+                        "csv" => Format::Csv,
+                        "json" => Format::Json,
+                        other => return Err(format!("unknown format: {}", other)),
+                    }
+                }
+                option if option.starts_with('-') && option != "-" => {
+                    return Err(format!("unknown option: {}", option))
+                }
+                _ => options.files.push(arg),
+            }
+        }
+        Ok(Some(options))
+    }
+}
+
+/// Reads `inputs` as one stream of little-endian words, and passes the words to `f` a buffer at a
+/// time, stopping at the first error `f` returns. The bytes of a word split between two reads or
+/// two inputs are carried over, and a trailing incomplete word is padded with zeros.
+fn read_words<T, I, R, F>(inputs: I, mut f: F) -> io::Result<()>
+where
+    T: Word,
+    I: IntoIterator<Item = io::Result<R>>,
+    R: Read,
+    F: FnMut(&[T]) -> io::Result<()>,
+{
+    let word_bytes = T::BITS / 8;
+    let mut buffer = vec![0; READ_BUFFER_LEN];
+    let mut words = Vec::with_capacity(READ_BUFFER_LEN / word_bytes);
+    // The bytes of an incomplete word at the front of the buffer
+    let mut pending = 0;
+    for input in inputs {
+        let mut input = input?;
+        loop {
+            let read = match input.read(&mut buffer[pending..]) {
+                Ok(0) => break,
+                Ok(read) => read,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            let filled = pending + read;
+            let mut chunks = buffer[..filled].chunks_exact(word_bytes);
+            words.clear();
+            words.extend((&mut chunks).map(T::from_le_bytes));
+            pending = chunks.remainder().len();
+            f(&words)?;
+            buffer.copy_within(filled - pending..filled, 0);
+        }
+    }
+    if pending > 0 {
+        buffer[pending..word_bytes].fill(0);
+        f(&[T::from_le_bytes(&buffer[..word_bytes])])?;
+    }
+    Ok(())
+}
+
+/// Counts the ones of the words in `inputs` with `counter`, and writes them as asked for by
+/// `options`. The words are counted a buffer at a time, and for `words` output their cardinalities
+/// are written as soon as they are counted, so only the histogram is kept in memory.
+fn write_counts<T, W, I, R>(
+    out: &mut W,
+    counter: &dyn BitCounter<T>,
+    inputs: I,
+    options: &Options,
+) -> io::Result<()>
+where
+    T: Word,
+    W: Write,
+    I: IntoIterator<Item = io::Result<R>>,
+    R: Read,
+{
+    if options.output == Output::Total {
+        let mut total = 0;
+        read_words(inputs, |words| {
+            total += counter.total(words);
+            Ok(())
+        })?;
+        return match options.format {
+            Format::Plain => writeln!(out, "{}", total),
+            Format::Csv => writeln!(out, "total\n{}", total),
+            Format::Json => writeln!(out, "{{\"total\":{}}}", total),
+        };
+    }
+    let mut buffer = Vec::new();
+    if options.output == Output::Words {
+        let mut table = Table::start(out, options.format, "word", "ones", "counts")?;
+        read_words(inputs, |words| {
+            buffer.resize(words.len(), 0);
+            counter.count(words, &mut buffer);
+            buffer.iter().try_for_each(|&count| table.row(count as u64))
+        })?;
+        return table.finish();
+    }
+    let mut histogram = vec![0; T::BITS + 1];
+    read_words(inputs, |words| {
+        buffer.resize(words.len(), 0);
+        counter.count(words, &mut buffer);
+        for &count in &buffer {
+            histogram[count as usize] += 1;
+        }
+        Ok(())
+    })?;
+    let mut table = Table::start(out, options.format, "ones", "words", "histogram")?;
+    histogram.iter().try_for_each(|&count| table.row(count))?;
+    table.finish()
+}
+
+/// Writes values with their indices a row at a time. JSON output is an object holding the values
+/// as an array under the name of the table.
+struct Table<'a, W> {
+    out: &'a mut W,
+    format: Format,
+    /// The number of rows written, which is the index of the next one
+    rows: usize,
+}
+
+impl<'a, W: Write> Table<'a, W> {
+    /// Writes the header of the table.
+    fn start(
+        out: &'a mut W,
+        format: Format,
+        index_header: &str,
+        value_header: &str,
+        name: &str,
+    ) -> io::Result<Self> {
+        match format {
+            Format::Plain => {}
+            Format::Csv => writeln!(out, "{},{}", index_header, value_header)?,
+            Format::Json => write!(out, "{{\"{}\":[", name)?,
+        }
+        Ok(Table {
+            out,
+            format,
+            rows: 0,
+        })
+    }
+
+    fn row(&mut self, value: u64) -> io::Result<()> {
+        let i = self.rows;
+        self.rows += 1;
+        match self.format {
+            Format::Plain => writeln!(self.out, "{}\t{}", i, value),
+            Format::Csv => writeln!(self.out, "{},{}", i, value),
+            Format::Json if i == 0 => write!(self.out, "{}", value),
+            Format::Json => write!(self.out, ",{}", value),
+        }
+    }
+
+    /// Closes the JSON array and object.
+    fn finish(self) -> io::Result<()> {
+        match self.format {
+            Format::Json => writeln!(self.out, "]}}"),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Opens `file`, or stdin if it is `-`.
+fn open(file: &str) -> io::Result<Box<dyn Read>> {
+    if file == "-" {
+        return Ok(Box::new(io::stdin().lock()));
+    }
+    File::open(file)
+        .map(|f| Box::new(f) as Box<dyn Read>)
+        .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", file, e)))
+}
+
+/// Counts the ones of the files in `options`, or of stdin if there are none, as words of type
+/// `T`, and writes them to stdout.
+fn run<T: Word + Registry>(options: &Options) -> Result<(), String> {
+    let counter = counter_by_name::<T>(&options.algorithm).ok_or_else(|| {
+        let names: Vec<&str> = T::counters().iter().map(|counter| counter.name()).collect();
+        format!(
+            "unknown algorithm for {}-bit words: {} (expected one of {})",
+            T::BITS,
+            options.algorithm,
+            names.join(", ")
+        )
+    })?;
+    let stdin = [String::from("-")];
+    let files = if options.files.is_empty() {
+        &stdin[..]
+    } else {
+        &options.files[..]
+    };
+    let inputs = files.iter().map(|file| open(file));
+    let stdout = io::stdout();
+    write_counts(&mut stdout.lock(), counter.as_ref(), inputs, options).map_err(|e| e.to_string())
+}
+
+fn main() {
+    let options = match Options::parse(std::env::args().skip(1)) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            println!("{}", USAGE);
+            return;
+        }
+        Err(e) => {
+            eprintln!("popcnt: {}\n\n{}", e, USAGE);
+            process::exit(2);
+        }
+    };
+    let result = match options.width {
+        16 => run::<u16>(&options),
+        32 => run::<u32>(&options),
+        64 => run::<u64>(&options),
+        _ => run::<u128>(&options),
+    };
+    if let Err(e) = result {
+        eprintln!("popcnt: {}", e);
+        process::exit(1);
+    }
+}
+
+#[test]
+fn test_algorithms_agree() {
+    use rand::Rng;
+    let mut rng = rand::thread_rng();
+    // 1001 bytes leave an incomplete word
+    let bytes: Vec<u8> = (0..1001).map(|_| rng.gen::<u8>()).collect();
+    for &output in &[Output::Total, Output::Words, Output::Histogram] {
+        let mut outputs = Vec::new();
+        for counter in u32::counters() {
+            let options = Options {
+                width: 32,
+                algorithm: String::from(counter.name()),
+                output,
+                format: Format::Plain,
+                files: Vec::new(),
+            };
+            let mut out = Vec::new();
+            write_counts(&mut out, counter.as_ref(), [Ok(&bytes[..])], &options).unwrap();
+            outputs.push(out);
+        }
+        for other in &outputs[1..] {
+            assert_eq!(*other, outputs[0], "{:?}", output);
+        }
+    }
+}
+
+#[test]
+fn test_output() {
+    use parallel_bit_counting::bit_counter::Builtin;
+    let args = "-w 16 -o histogram -f json a -"
+        .split(' ')
+        .map(String::from);
+    let options = Options::parse(args).unwrap().unwrap();
+    assert_eq!(options.files, vec!["a", "-"]);
+    let mut out = Vec::new();
+    let bytes: Vec<u8> = [1u16, u16::MAX, 2]
+        .iter()
+        .flat_map(|word| word.to_le_bytes())
+        .collect();
+    let counter: &dyn BitCounter<u16> = &Builtin;
+    write_counts(&mut out, counter, [Ok(&bytes[..])], &options).unwrap();
+    let mut expected = String::from("{\"histogram\":[0,2");
+    expected += &",0".repeat(14);
+    expected += ",1]}\n";
+    assert_eq!(String::from_utf8(out).unwrap(), expected);
+
+    let options = Options::parse("-o words -f csv".split(' ').map(String::from))
+        .unwrap()
+        .unwrap();
+    let mut out = Vec::new();
+    let bytes: Vec<u8> = [7u64, 31]
+        .iter()
+        .flat_map(|word| word.to_le_bytes())
+        .collect();
+    let counter: &dyn BitCounter<u64> = &Builtin;
+    write_counts(&mut out, counter, [Ok(&bytes[..])], &options).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(), "word,ones\n0,3\n1,5\n");
+    let options = Options::parse("-o words -f json".split(' ').map(String::from))
+        .unwrap()
+        .unwrap();
+    let mut out = Vec::new();
+    write_counts(&mut out, counter, [Ok(&bytes[..])], &options).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(), "{\"counts\":[3,5]}\n");
+
+    assert!(Options::parse("-w 8".split(' ').map(String::from)).is_err());
+    assert!(Options::parse("--width".split(' ').map(String::from)).is_err());
+}
+
+#[test]
+fn test_read_words() {
+    use rand::Rng;
+    use std::convert::TryInto;
+    let mut rng = rand::thread_rng();
+    // More than one buffer, ending in an incomplete word
+    let bytes: Vec<u8> = (0..READ_BUFFER_LEN * 2 + 13)
+        .map(|_| rng.gen::<u8>())
+        .collect();
+    let mut expected: Vec<u128> = bytes
+        .chunks_exact(16)
+        .map(|chunk| u128::from_le_bytes(chunk.try_into().unwrap()))
+        .collect();
+    let mut last = [0; 16];
+    last[..13].copy_from_slice(&bytes[bytes.len() - 13..]);
+    expected.push(u128::from_le_bytes(last));
+    // Inputs that split words between them
+    for &splits in &[&[][..], &[1, 2, 3], &[7, READ_BUFFER_LEN + 5, 100]] {
+        let mut inputs = Vec::new();
+        let mut start = 0;
+        for &split in splits {
+            inputs.push(Ok(&bytes[start..start + split]));
+            start += split;
+        }
+        inputs.push(Ok(&bytes[start..]));
+        let mut words = Vec::new();
+        read_words(inputs, |chunk: &[u128]| {
+            words.extend_from_slice(chunk);
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(words, expected, "{:?}", splits);
+    }
+}
@@ -0,0 +1,355 @@
+use crate::adaptive_count_ones::adaptive_count_ones;
+use crate::adaptive_count_ones::adaptive_count_ones_total;
+use crate::adaptive_count_ones::DensityConfig;
+use crate::calculate_mask::GetMask;
+use crate::count_ones::count_ones_narrow_into;
+use crate::count_ones::count_ones_total;
+use crate::count_ones::Word;
+use crate::divide_and_conquer_count_ones::multiply_parallel_count_ones_128;
+use crate::divide_and_conquer_count_ones::multiply_parallel_count_ones_16;
+use crate::divide_and_conquer_count_ones::multiply_parallel_count_ones_32;
+use crate::divide_and_conquer_count_ones::multiply_parallel_count_ones_64;
+use crate::divide_and_conquer_count_ones::naive_parallel_count_ones_128;
+use crate::divide_and_conquer_count_ones::naive_parallel_count_ones_16;
+use crate::divide_and_conquer_count_ones::naive_parallel_count_ones_32;
+use crate::divide_and_conquer_count_ones::naive_parallel_count_ones_64;
+use crate::harley_seal_count_ones::harley_seal_count_ones;
+use crate::kernighan_count_ones::kernighan_count_ones_total;
+use crate::kernighan_count_ones::kernighan_count_ones_word;
+use crate::lookup_table_count_ones::lookup_16_count_ones_into;
+use crate::lookup_table_count_ones::lookup_16_count_ones_total;
+use crate::lookup_table_count_ones::lookup_8_count_ones_into;
+use crate::lookup_table_count_ones::lookup_8_count_ones_total;
+use crate::multithreaded_count_ones::count_ones_multithreaded;
+use crate::multithreaded_count_ones::ThreadConfig;
+use crate::naive_count_ones::naive_count_bits_128;
+use crate::naive_count_ones::naive_count_bits_16;
+use crate::naive_count_ones::naive_count_bits_32;
+use crate::naive_count_ones::naive_count_bits_64;
+use crate::simd;
+
+/// An algorithm that counts the ones of words of type `T`.
+pub trait BitCounter<T> {
+    /// The name the algorithm is registered under
+    fn name(&self) -> &'static str;
+
+    /// Counts the ones of every word in `experiment` and writes the cardinalities to `out`, which
+    /// must be exactly as long as `experiment`.
+    fn count(&self, experiment: &[T], out: &mut [u8]);
+
+    /// Counts the total number of ones in `experiment`.
+    fn total(&self, experiment: &[T]) -> u64 {
+        let mut out = vec![0; experiment.len()];
+        self.count(experiment, &mut out);
+        out.iter().map(|&count| count as u64).sum()
+    }
+}
+
+/// The word types with a registry of every [`BitCounter`] that supports them.
+pub trait Registry: Sized {
+    fn counters() -> Vec<Box<dyn BitCounter<Self>>>;
+}
+
+/// Returns the registered counter of `T` called `name`.
+pub fn counter_by_name<T: Registry>(name: &str) -> Option<Box<dyn BitCounter<T>>> {
+    T::counters()
+        .into_iter()
+        .find(|counter| counter.name() == name)
+}
+
+/// Writes cardinalities that are stored as words to `out`.
+fn narrow<T: Word>(counts: &[T], out: &mut [u8]) {
+    assert_eq!(
+        counts.len(),
+        out.len(),
+        "the output buffer must be as long as the input"
+    );
+    for (out, count) in out.iter_mut().zip(counts.iter()) {
+        *out = count.as_u64() as u8;
+    }
+}
+
+/// Counts one bit at a time, with `naive_count_bits_*`.
+pub struct Naive;
+
+/// Sums blocks of doubling width in every word, with `naive_parallel_count_ones_*`.
+pub struct DivideAndConquer;
+
+/// [`DivideAndConquer`] up to the cardinalities of the bytes, which are summed with a
+/// multiplication, with `multiply_parallel_count_ones_*`.
+pub struct DivideAndConquerMultiply;
+
+/// Packs the cardinalities of several words into one, with `count_ones`.
+pub struct Packed;
+
+/// The `count_ones` instruction of the CPU.
+pub struct Builtin;
+
+/// The Harley-Seal carry-save adder tree. It only speeds up the total, so the cardinalities of
+/// single words are counted like [`Packed`].
+pub struct HarleySeal;
+
+/// [`Packed`] on the threads given by the config.
+#[derive(Default)]
+pub struct Multithreaded(pub ThreadConfig);
+
+/// [`Packed`] on the widest SIMD backend the CPU supports.
+pub struct Simd;
+
+/// Kernighan's loop, which takes one iteration per one.
+pub struct Kernighan;
+
+/// Kernighan's loop for the blocks that are sampled to be sparse, and [`Packed`] for the others.
+#[derive(Default)]
+pub struct Adaptive(pub DensityConfig);
+
+/// Looks up the cardinality of every byte in a table.
+pub struct Lookup8;
+
+/// Looks up the cardinality of every 16 bits in a table.
+pub struct Lookup16;
+
+impl<T: Word + GetMask> BitCounter<T> for Packed {
+    fn name(&self) -> &'static str {
+        "packed"
+    }
+    fn count(&self, experiment: &[T], out: &mut [u8]) {
+        count_ones_narrow_into(experiment, &T::MASKS, out);
+    }
+    fn total(&self, experiment: &[T]) -> u64 {
+        count_ones_total(experiment)
+    }
+}
+
+impl<T: Word + GetMask> BitCounter<T> for HarleySeal {
+    fn name(&self) -> &'static str {
+        "harley-seal"
+    }
+    fn count(&self, experiment: &[T], out: &mut [u8]) {
+        count_ones_narrow_into(experiment, &T::MASKS, out);
+    }
+    fn total(&self, experiment: &[T]) -> u64 {
+        harley_seal_count_ones(experiment)
+    }
+}
+
+impl<T: Word + GetMask + Send + Sync> BitCounter<T> for Multithreaded {
+    fn name(&self) -> &'static str {
+        "multithreaded"
+    }
+    fn count(&self, experiment: &[T], out: &mut [u8]) {
+        narrow(
+            &count_ones_multithreaded(experiment, &T::MASKS, &self.0),
+            out,
+        );
+    }
+}
+
+impl<T: Word> BitCounter<T> for Lookup8 {
+    fn name(&self) -> &'static str {
+        "lookup-8"
+    }
+    fn count(&self, experiment: &[T], out: &mut [u8]) {
+        lookup_8_count_ones_into(experiment, out);
+    }
+    fn total(&self, experiment: &[T]) -> u64 {
+        lookup_8_count_ones_total(experiment)
+    }
+}
+
+impl<T: Word> BitCounter<T> for Lookup16 {
+    fn name(&self) -> &'static str {
+        "lookup-16"
+    }
+    fn count(&self, experiment: &[T], out: &mut [u8]) {
+        lookup_16_count_ones_into(experiment, out);
+    }
+    fn total(&self, experiment: &[T]) -> u64 {
+        lookup_16_count_ones_total(experiment)
+    }
+}
+
+impl<T: Word> BitCounter<T> for Kernighan {
+    fn name(&self) -> &'static str {
+        "kernighan"
+    }
+    fn count(&self, experiment: &[T], out: &mut [u8]) {
+        assert_eq!(
+            experiment.len(),
+            out.len(),
+            "the output buffer must be as long as the input"
+        );
+        for (out, word) in out.iter_mut().zip(experiment.iter()) {
+            *out = kernighan_count_ones_word(*word) as u8;
+        }
+    }
+    fn total(&self, experiment: &[T]) -> u64 {
+        kernighan_count_ones_total(experiment)
+    }
+}
+
+impl<T: Word + GetMask> BitCounter<T> for Adaptive {
+    fn name(&self) -> &'static str {
+        "adaptive"
+    }
+    fn count(&self, experiment: &[T], out: &mut [u8]) {
+        narrow(&adaptive_count_ones(experiment, &self.0), out);
+    }
+    fn total(&self, experiment: &[T]) -> u64 {
+        adaptive_count_ones_total(experiment, &self.0)
+    }
+}
+
+impl BitCounter<u64> for Simd {
+    fn name(&self) -> &'static str {
+        "simd"
+    }
+    fn count(&self, experiment: &[u64], out: &mut [u8]) {
+        let mut counts = vec![0; experiment.len()];
+        simd::count_ones_into(experiment, &mut counts);
+        narrow(&counts, out);
+    }
+}
+
+macro_rules! register {
+    ($t:ty, $naive:ident, $divide_and_conquer:ident, $multiply:ident $(, $extra:expr)*) => {
+        impl BitCounter<$t> for Naive {
+            fn name(&self) -> &'static str {
+                "naive"
+            }
+            fn count(&self, experiment: &[$t], out: &mut [u8]) {
+                let mut set = experiment.to_vec();
+                $naive(&mut set);
+                narrow(&set, out);
+            }
+        }
+        impl BitCounter<$t> for DivideAndConquer {
+            fn name(&self) -> &'static str {
+                "divide-and-conquer"
+            }
+            fn count(&self, experiment: &[$t], out: &mut [u8]) {
+                narrow(&$divide_and_conquer(experiment), out);
+            }
+        }
+        impl BitCounter<$t> for DivideAndConquerMultiply {
+            fn name(&self) -> &'static str {
+                "divide-and-conquer-multiply"
+            }
+            fn count(&self, experiment: &[$t], out: &mut [u8]) {
+                narrow(&$multiply(experiment), out);
+            }
+        }
+        impl BitCounter<$t> for Builtin {
+            fn name(&self) -> &'static str {
+                "builtin"
+            }
+            fn count(&self, experiment: &[$t], out: &mut [u8]) {
+                assert_eq!(
+                    experiment.len(),
+                    out.len(),
+                    "the output buffer must be as long as the input"
+                );
+                for (out, word) in out.iter_mut().zip(experiment.iter()) {
+                    *out = word.count_ones() as u8;
+                }
+            }
+            fn total(&self, experiment: &[$t]) -> u64 {
+                experiment.iter().map(|word| word.count_ones() as u64).sum()
+            }
+        }
+        impl Registry for $t {
+            fn counters() -> Vec<Box<dyn BitCounter<$t>>> {
+                vec![
+                    Box::new(Naive),
+                    Box::new(DivideAndConquer),
+                    Box::new(DivideAndConquerMultiply),
+                    Box::new(Packed),
+                    Box::new(Builtin),
+                    Box::new(HarleySeal),
+                    Box::new(Multithreaded::default()),
+                    Box::new(Lookup8),
+                    Box::new(Lookup16),
+                    Box::new(Kernighan),
+                    Box::new(Adaptive::default()),
+                    $(Box::new($extra),)*
+                ]
+            }
+        }
+    };
+}
+register!(
+    u16,
+    naive_count_bits_16,
+    naive_parallel_count_ones_16,
+    multiply_parallel_count_ones_16
+);
+register!(
+    u32,
+    naive_count_bits_32,
+    naive_parallel_count_ones_32,
+    multiply_parallel_count_ones_32
+);
+register!(
+    u64,
+    naive_count_bits_64,
+    naive_parallel_count_ones_64,
+    multiply_parallel_count_ones_64,
+    Simd
+);
+register!(
+    u128,
+    naive_count_bits_128,
+    naive_parallel_count_ones_128,
+    multiply_parallel_count_ones_128
+);
+
+#[cfg(test)]
+fn check_counters<T: Registry + Word>(experiment: &[T], expected: &[u8]) {
+    let expected_total: u64 = expected.iter().map(|&count| count as u64).sum();
+    let counters = T::counters();
+    for (i, counter) in counters.iter().enumerate() {
+        let mut out = vec![0; experiment.len()];
+        counter.count(experiment, &mut out);
+        assert_eq!(out, expected, "{}, {}-bit", counter.name(), T::BITS);
+        assert_eq!(
+            counter.total(experiment),
+            expected_total,
+            "{}, {}-bit",
+            counter.name(),
+            T::BITS
+        );
+        assert!(counters[..i]
+            .iter()
+            .all(|other| other.name() != counter.name()));
+        assert!(counter_by_name::<T>(counter.name()).is_some());
+    }
+}
+
+#[test]
+fn test_every_counter() {
+    use rand::Rng;
+    let mut rng = rand::thread_rng();
+    let len = 1001;
+    let val: Vec<u16> = (0..len).map(|_| rng.gen()).collect();
+    check_counters(
+        &val,
+        &val.iter().map(|x| x.count_ones() as u8).collect::<Vec<_>>(),
+    );
+    let val: Vec<u32> = (0..len).map(|_| rng.gen()).collect();
+    check_counters(
+        &val,
+        &val.iter().map(|x| x.count_ones() as u8).collect::<Vec<_>>(),
+    );
+    let val: Vec<u64> = (0..len).map(|_| rng.gen()).collect();
+    check_counters(
+        &val,
+        &val.iter().map(|x| x.count_ones() as u8).collect::<Vec<_>>(),
+    );
+    let val: Vec<u128> = (0..len).map(|_| rng.gen()).collect();
+    check_counters(
+        &val,
+        &val.iter().map(|x| x.count_ones() as u8).collect::<Vec<_>>(),
+    );
+    assert!(counter_by_name::<u64>("simd").is_some());
+    assert!(counter_by_name::<u128>("simd").is_none());
+}
@@ -0,0 +1,160 @@
+use crate::calculate_mask::GetMask;
+use crate::count_ones::count_ones_into;
+use crate::count_ones::count_ones_total;
+use crate::count_ones::naive_pack_word;
+use std::mem::size_of;
+
+/// The word the aligned middle of a byte buffer is counted as, the widest word the CPU adds and
+/// shifts with a single instruction.
+type Block = u64;
+
+/// The number of bytes in a block
+pub const BLOCK_BYTES: usize = size_of::<Block>();
+
+/// The number of blocks `count_ones_blocks_into` counts at a time
+const BLOCK_BUFFER_LEN: usize = 64;
+
+/// Splits `bytes` into an unaligned head, an aligned middle of blocks and an unaligned tail.
+fn split_aligned(bytes: &[u8]) -> (&[u8], &[Block], &[u8]) {
+    // SAFETY: every pattern of BLOCK_BYTES bytes is a valid Block
+    unsafe { bytes.align_to::<Block>() }
+}
+
+/// Reads at most BLOCK_BYTES bytes as a little-endian block, padded with zero bytes.
+fn partial_block(bytes: &[u8]) -> Block {
+    let mut block = [0; BLOCK_BYTES];
+    block[..bytes.len()].copy_from_slice(bytes);
+    Block::from_le_bytes(block)
+}
+
+/// Counts the total number of ones in `bytes`.
+///
+/// The head and tail that are not aligned to a block are counted as `u8` words, and the middle is
+/// counted as blocks, without copying it.
+pub fn count_ones_bytes_total(bytes: &[u8]) -> u64 {
+    let (head, middle, tail) = split_aligned(bytes);
+    count_ones_total(head) + count_ones_total(middle) + count_ones_total(tail)
+}
+
+/// Counts the ones of every byte in `bytes` and writes the cardinalities to `out`, which must be
+/// exactly as long as `bytes`.
+pub fn count_ones_bytes_into(bytes: &[u8], out: &mut [u8]) {
+    assert_eq!(
+        bytes.len(),
+        out.len(),
+        "the output buffer must be as long as the input"
+    );
+    let (head, middle, tail) = split_aligned(bytes);
+    let (out_head, out_rest) = out.split_at_mut(head.len());
+    let (out_middle, out_tail) = out_rest.split_at_mut(middle.len() * BLOCK_BYTES);
+    count_ones_into(head, &u8::MASKS, out_head);
+    let masks = &Block::MASKS;
+    for (block, out) in middle.iter().zip(out_middle.chunks_exact_mut(BLOCK_BYTES)) {
+        // After the first three packing steps, every byte holds its own cardinality
+        let mut packed = *block;
+        for i in 0..3 {
+            packed = naive_pack_word(&packed, i, masks.get(i, i + 1));
+        }
+        out.copy_from_slice(&packed.to_ne_bytes());
+    }
+    count_ones_into(tail, &u8::MASKS, out_tail);
+}
+
+/// Counts the ones of every byte in `bytes`.
+pub fn count_ones_bytes(bytes: &[u8]) -> Vec<u8> {
+    let mut acc = vec![0; bytes.len()];
+    count_ones_bytes_into(bytes, &mut acc);
+    acc
+}
+
+/// Counts the ones of every block of [`BLOCK_BYTES`] bytes in `bytes`, such that `out[i]` is the
+/// cardinality of `bytes[i * BLOCK_BYTES..(i + 1) * BLOCK_BYTES]`. The last block may be shorter.
+///
+/// The blocks are counted from the start of `bytes`, wherever it is aligned, by shifting them
+/// together from the aligned blocks of the middle. `out` must have a cardinality for every block.
+pub fn count_ones_blocks_into(bytes: &[u8], out: &mut [u64]) {
+    assert_eq!(
+        out.len(),
+        bytes.len().div_ceil(BLOCK_BYTES),
+        "the output buffer must have a cardinality for every block"
+    );
+    let (head, middle, tail) = split_aligned(bytes);
+    if middle.is_empty() {
+        for (block, bytes) in out.iter_mut().zip(bytes.chunks(BLOCK_BYTES)) {
+            *block = partial_block(bytes);
+        }
+    } else {
+        // Every block is made of the last head.len() bytes of an aligned block and the first
+        // bytes of the next one
+        let shift = 8 * head.len();
+        let mut carry = partial_block(head);
+        let (out_middle, out_rest) = out.split_at_mut(middle.len());
+        for (block, aligned) in out_middle.iter_mut().zip(middle.iter()) {
+            let aligned = Block::from_le(*aligned);
+            if shift == 0 {
+                *block = aligned;
+            } else {
+                *block = carry | aligned << shift;
+                carry = aligned >> (8 * BLOCK_BYTES - shift);
+            }
+        }
+        // The carried bytes and the tail make up the last one or two blocks
+        let mut rest = [0; 2 * BLOCK_BYTES];
+        rest[..head.len()].copy_from_slice(&carry.to_le_bytes()[..head.len()]);
+        rest[head.len()..head.len() + tail.len()].copy_from_slice(tail);
+        for (block, bytes) in out_rest.iter_mut().zip(rest.chunks(BLOCK_BYTES)) {
+            *block = partial_block(bytes);
+        }
+    }
+    let mut buffer = [0; BLOCK_BUFFER_LEN];
+    for chunk in out.chunks_mut(BLOCK_BUFFER_LEN) {
+        let blocks = &mut buffer[..chunk.len()];
+        blocks.copy_from_slice(chunk);
+        count_ones_into(blocks, &Block::MASKS, chunk);
+    }
+}
+
+/// Counts the ones of every block of [`BLOCK_BYTES`] bytes in `bytes`.
+pub fn count_ones_blocks(bytes: &[u8]) -> Vec<u64> {
+    let mut acc = vec![0; bytes.len().div_ceil(BLOCK_BYTES)];
+    count_ones_blocks_into(bytes, &mut acc);
+    acc
+}
+
+#[test]
+fn test_every_offset() {
+    use rand::Rng;
+    let mut rng = rand::thread_rng();
+    let buffer: Vec<u8> = (0..1024 + 16).map(|_| rng.gen::<u8>()).collect();
+    for offset in 0..16 {
+        for &len in &[0, 1, 7, 8, 9, 15, 16, 17, 31, 100, 1024] {
+            let bytes = &buffer[offset..offset + len];
+            let expected: Vec<u8> = bytes.iter().map(|x| x.count_ones() as u8).collect();
+            assert_eq!(
+                count_ones_bytes(bytes),
+                expected,
+                "offset: {}, len: {}",
+                offset,
+                len
+            );
+            let expected: Vec<u64> = bytes
+                .chunks(BLOCK_BYTES)
+                .map(|block| block.iter().map(|x| x.count_ones() as u64).sum())
+                .collect();
+            assert_eq!(
+                count_ones_blocks(bytes),
+                expected,
+                "offset: {}, len: {}",
+                offset,
+                len
+            );
+            assert_eq!(
+                count_ones_bytes_total(bytes),
+                expected.iter().sum::<u64>(),
+                "offset: {}, len: {}",
+                offset,
+                len
+            );
+        }
+    }
+}
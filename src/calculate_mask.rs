@@ -1,31 +1,121 @@
 use crate::count_ones::Word;
 
-pub trait GetMask: Sized {
-    fn get_mask() -> [[Self; 8]; 8];
+/// The number of rows and columns in a [`Masks`] table, which supports words of up to
+/// 2^(MASK_TABLE_LEN - 1) bits.
+pub const MASK_TABLE_LEN: usize = 10;
+
+/// The masks m_{i,j}, the conjunction of the basic masks i through j - 1, for a single word type.
+///
+/// The only instances are the tables computed at compile time, such as [`MASKS_U64`] or
+/// `u64::MASKS`, so a `Masks` can not be filled wrongly.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Masks<T> {
+    table: [[T; MASK_TABLE_LEN]; MASK_TABLE_LEN],
 }
-impl GetMask for u16 {
-    fn get_mask() -> [[u16; 8]; 8] {
-        get_mask(get_basic_masks_u16())
+impl<T> Masks<T> {
+    pub(crate) const fn from_table(table: [[T; MASK_TABLE_LEN]; MASK_TABLE_LEN]) -> Self {
+        Masks { table }
+    }
+    /// Returns m_{i,j}. The mask is zero unless i < j.
+    #[inline(always)]
+    pub const fn get(&self, i: usize, j: usize) -> &T {
+        &self.table[i][j]
     }
 }
-impl GetMask for u32 {
-    fn get_mask() -> [[u32; 8]; 8] {
-        get_mask(get_basic_masks_u32())
+impl<T: Word> Masks<T> {
+    /// Wraps a table built by [`get_mask`], for words of at most 128 bits.
+    pub(crate) fn from_legacy(legacy: [[T; 8]; 8]) -> Self {
+        let mut table = [[T::zero(); MASK_TABLE_LEN]; MASK_TABLE_LEN];
+        for (row, legacy_row) in table.iter_mut().zip(legacy.iter()) {
+            row[..8].copy_from_slice(legacy_row);
+        }
+        Masks { table }
     }
 }
-impl GetMask for u64 {
-    fn get_mask() -> [[u64; 8]; 8] {
-        get_mask(get_basic_masks_u64())
+
+pub trait GetMask: Copy {
+    const MASKS: Masks<Self>;
+    fn get_mask() -> [[Self; 8]; 8] {
+        let mut array = [[Self::MASKS.table[0][0]; 8]; 8];
+        for (row, table_row) in array.iter_mut().zip(Self::MASKS.table.iter()) {
+            row.copy_from_slice(&table_row[..8]);
+        }
+        array
     }
 }
+impl GetMask for u8 {
+    const MASKS: Masks<u8> = MASKS_U8;
+}
+impl GetMask for u16 {
+    const MASKS: Masks<u16> = MASKS_U16;
+}
+impl GetMask for u32 {
+    const MASKS: Masks<u32> = MASKS_U32;
+}
+impl GetMask for u64 {
+    const MASKS: Masks<u64> = MASKS_U64;
+}
 impl GetMask for u128 {
-    fn get_mask() -> [[u128; 8]; 8] {
-        get_mask(get_basic_masks_u128())
-    }
+    const MASKS: Masks<u128> = MASKS_U128;
+}
+impl GetMask for usize {
+    const MASKS: Masks<usize> = MASKS_USIZE;
+}
+
+macro_rules! const_mask_table {
+    ($name:ident, $t:ty) => {
+        /// Computes the same table as [`get_mask`], but in a const context.
+        /// The basic masks past the 8 given are all ones.
+        const fn $name(basic_masks: [$t; 8]) -> [[$t; MASK_TABLE_LEN]; MASK_TABLE_LEN] {
+            let mut array = [[0; MASK_TABLE_LEN]; MASK_TABLE_LEN];
+            let mut i = 0;
+            while i < MASK_TABLE_LEN {
+                let mut j = i + 1;
+                while j < MASK_TABLE_LEN {
+                    let mut k = i;
+                    array[i][j] = !0;
+                    while k < j {
+                        if k < 8 {
+                            array[i][j] &= basic_masks[k];
+                        }
+                        k += 1;
+                    }
+                    j += 1;
+                }
+                i += 1;
+            }
+            array
+        }
+    };
 }
+const_mask_table!(mask_table_u8, u8);
+const_mask_table!(mask_table_u16, u16);
+const_mask_table!(mask_table_u32, u32);
+const_mask_table!(mask_table_u64, u64);
+const_mask_table!(mask_table_u128, u128);
+const_mask_table!(mask_table_usize, usize);
+
+pub const MASKS_U8: Masks<u8> = Masks {
+    table: mask_table_u8(get_basic_masks_u8()),
+};
+pub const MASKS_U16: Masks<u16> = Masks {
+    table: mask_table_u16(get_basic_masks_u16()),
+};
+pub const MASKS_U32: Masks<u32> = Masks {
+    table: mask_table_u32(get_basic_masks_u32()),
+};
+pub const MASKS_U64: Masks<u64> = Masks {
+    table: mask_table_u64(get_basic_masks_u64()),
+};
+pub const MASKS_U128: Masks<u128> = Masks {
+    table: mask_table_u128(get_basic_masks_u128()),
+};
+pub const MASKS_USIZE: Masks<usize> = Masks {
+    table: mask_table_usize(get_basic_masks_usize()),
+};
 
 #[inline(always)]
-pub fn get_basic_masks_u128() -> [u128; 8] {
+pub const fn get_basic_masks_u128() -> [u128; 8] {
     [
         0b01010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101u128,
         0b00110011001100110011001100110011001100110011001100110011001100110011001100110011001100110011001100110011001100110011001100110011u128,
@@ -39,7 +129,7 @@ pub fn get_basic_masks_u128() -> [u128; 8] {
 }
 
 #[inline(always)]
-pub fn get_basic_masks_u64() -> [u64; 8] {
+pub const fn get_basic_masks_u64() -> [u64; 8] {
     [
         0b0101010101010101010101010101010101010101010101010101010101010101u64,
         0b0011001100110011001100110011001100110011001100110011001100110011u64,
@@ -53,7 +143,7 @@ pub fn get_basic_masks_u64() -> [u64; 8] {
 }
 
 #[inline(always)]
-pub fn get_basic_masks_u32() -> [u32; 8] {
+pub const fn get_basic_masks_u32() -> [u32; 8] {
     [
         0b01010101010101010101010101010101u32,
         0b00110011001100110011001100110011u32,
@@ -66,7 +156,32 @@ pub fn get_basic_masks_u32() -> [u32; 8] {
     ]
 }
 #[inline(always)]
-pub fn get_basic_masks_u16() -> [u16; 8] {
+pub const fn get_basic_masks_u8() -> [u8; 8] {
+    [
+        0b01010101u8,
+        0b00110011u8,
+        0b00001111u8,
+        0b11111111u8,
+        0b11111111u8,
+        0b11111111u8,
+        0b11111111u8,
+        0b11111111u8,
+    ]
+}
+#[inline(always)]
+pub const fn get_basic_masks_usize() -> [usize; 8] {
+    // Truncating the 64-bit masks gives the masks of a 32-bit usize
+    let masks = get_basic_masks_u64();
+    let mut array = [0; 8];
+    let mut i = 0;
+    while i < 8 {
+        array[i] = masks[i] as usize;
+        i += 1;
+    }
+    array
+}
+#[inline(always)]
+pub const fn get_basic_masks_u16() -> [u16; 8] {
     [
         0b0101010101010101u16,
         0b0011001100110011u16,
@@ -81,14 +196,11 @@ pub fn get_basic_masks_u16() -> [u16; 8] {
 
 pub fn get_mask<T: Word>(basic_masks: [T; 8]) -> [[T; 8]; 8] {
     let mut array: [[T; 8]; 8] = [[T::zero(); 8]; 8];
-    for i in 0..8 {
-        for j in 0..8 {
-            if j > i {
-                array[i][j] = basic_masks[i];
-                for k in i..j {
-                    array[i][j] = array[i][j] & basic_masks[k];
-                }
-            }
+    for (i, row) in array.iter_mut().enumerate() {
+        for (j, mask) in row.iter_mut().enumerate().skip(i + 1) {
+            *mask = basic_masks[i..j]
+                .iter()
+                .fold(basic_masks[i], |acc, &basic_mask| acc & basic_mask);
         }
     }
     array
@@ -157,3 +269,13 @@ fn test_mask_u64() {
         0b0000000000000000000000000000000011111111111111111111111111111111u64
     );
 }
+
+#[test]
+fn test_const_tables_match_get_mask() {
+    assert_eq!(u8::get_mask(), get_mask(get_basic_masks_u8()));
+    assert_eq!(u16::get_mask(), get_mask(get_basic_masks_u16()));
+    assert_eq!(u32::get_mask(), get_mask(get_basic_masks_u32()));
+    assert_eq!(u64::get_mask(), get_mask(get_basic_masks_u64()));
+    assert_eq!(u128::get_mask(), get_mask(get_basic_masks_u128()));
+    assert_eq!(usize::get_mask(), get_mask(get_basic_masks_usize()));
+}
@@ -1,11 +1,18 @@
+use crate::calculate_mask::GetMask;
+use crate::calculate_mask::Masks;
 use core::fmt::Binary;
 use core::fmt::Debug;
+use std::convert::TryInto;
 use std::ops::Add;
 use std::ops::BitAnd;
+use std::ops::BitOr;
+use std::ops::BitXor;
 use std::ops::Not;
 use std::ops::Shl;
 use std::ops::Shr;
+use std::ops::Sub;
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum WordLength {
     U16,
     U32,
@@ -15,7 +22,10 @@ pub enum WordLength {
 
 pub trait Word:
     Add<Output = Self>
+    + Sub<Output = Self>
     + BitAnd<Output = Self>
+    + BitOr<Output = Self>
+    + BitXor<Output = Self>
     + Shl<usize, Output = Self>
     + Shr<usize, Output = Self>
     + Not<Output = Self>
@@ -26,27 +36,126 @@ pub trait Word:
 where
     Self: std::marker::Sized,
 {
+    /// log_2 of the number of bits in the word
+    const LOG_D: usize;
+    /// The number of bits in the word
+    const BITS: usize = 1 << Self::LOG_D;
     fn zero() -> Self;
+    fn one() -> Self;
+    /// Truncates the word to its lowest 64 bits
+    fn as_u64(self) -> u64;
+    /// Builds a word holding `value`, truncated to the word if it is narrower than 64 bits
+    fn from_u64(value: u64) -> Self;
+    /// Builds a word from exactly `BITS / 8` little-endian bytes
+    fn from_le_bytes(bytes: &[u8]) -> Self;
+}
+impl Word for u8 {
+    const LOG_D: usize = 3;
+    fn zero() -> u8 {
+        0
+    }
+    fn one() -> u8 {
+        1
+    }
+    fn as_u64(self) -> u64 {
+        self as u64
+    }
+    fn from_u64(value: u64) -> u8 {
+        value as u8
+    }
+    fn from_le_bytes(bytes: &[u8]) -> u8 {
+        u8::from_le_bytes(bytes.try_into().expect("a word takes BITS / 8 bytes"))
+    }
 }
 impl Word for u16 {
+    const LOG_D: usize = 4;
     fn zero() -> u16 {
         0
     }
+    fn one() -> u16 {
+        1
+    }
+    fn as_u64(self) -> u64 {
+        self as u64
+    }
+    fn from_u64(value: u64) -> u16 {
+        value as u16
+    }
+    fn from_le_bytes(bytes: &[u8]) -> u16 {
+        u16::from_le_bytes(bytes.try_into().expect("a word takes BITS / 8 bytes"))
+    }
 }
 impl Word for u32 {
+    const LOG_D: usize = 5;
     fn zero() -> u32 {
         0
     }
+    fn one() -> u32 {
+        1
+    }
+    fn as_u64(self) -> u64 {
+        self as u64
+    }
+    fn from_u64(value: u64) -> u32 {
+        value as u32
+    }
+    fn from_le_bytes(bytes: &[u8]) -> u32 {
+        u32::from_le_bytes(bytes.try_into().expect("a word takes BITS / 8 bytes"))
+    }
 }
 impl Word for u64 {
+    const LOG_D: usize = 6;
     fn zero() -> u64 {
         0
     }
+    fn one() -> u64 {
+        1
+    }
+    fn as_u64(self) -> u64 {
+        self
+    }
+    fn from_u64(value: u64) -> u64 {
+        value
+    }
+    fn from_le_bytes(bytes: &[u8]) -> u64 {
+        u64::from_le_bytes(bytes.try_into().expect("a word takes BITS / 8 bytes"))
+    }
+}
+impl Word for usize {
+    const LOG_D: usize = 3 + std::mem::size_of::<usize>().trailing_zeros() as usize;
+    fn zero() -> usize {
+        0
+    }
+    fn one() -> usize {
+        1
+    }
+    fn as_u64(self) -> u64 {
+        self as u64
+    }
+    fn from_u64(value: u64) -> usize {
+        value as usize
+    }
+    fn from_le_bytes(bytes: &[u8]) -> usize {
+        usize::from_le_bytes(bytes.try_into().expect("a word takes BITS / 8 bytes"))
+    }
 }
 impl Word for u128 {
+    const LOG_D: usize = 7;
     fn zero() -> u128 {
         0
     }
+    fn one() -> u128 {
+        1
+    }
+    fn as_u64(self) -> u64 {
+        self as u64
+    }
+    fn from_u64(value: u64) -> u128 {
+        value as u128
+    }
+    fn from_le_bytes(bytes: &[u8]) -> u128 {
+        u128::from_le_bytes(bytes.try_into().expect("a word takes BITS / 8 bytes"))
+    }
 }
 
 #[inline(always)]
@@ -65,17 +174,10 @@ fn combine_words<T: Word>(word1: T, word2: T, i: usize) -> T {
 }
 
 #[inline(always)]
-fn calculate_l(i: usize) -> usize {
-    // The "proper" way to do this is to do ceil(log_2(i + 2)), but we hardcode it to save the log_2 computation
-    // On 64-bit words, i will never exceed 7
-    match i + 2 {
-        2 => 1,
-        3..=4 => 2,
-        5..=8 => 3,
-        9..=16 => 4,
-        16..=32 => 5,
-        _ => panic!("i was out of bounds"),
-    }
+const fn calculate_l(i: usize) -> usize {
+    // ceil(log_2(i + 2)) is the number of bits needed to represent i + 1,
+    // which we can read off the leading zeros instead of computing a log_2
+    8 * std::mem::size_of::<usize>() - (i + 1).leading_zeros() as usize
 }
 #[test]
 fn test_calculate_l() {
@@ -96,59 +198,419 @@ fn pack_word<T: Word>(word: &T, i: usize, mask: &T) -> T {
 }
 
 #[inline(always)]
-fn naive_pack_word<T: Word>(word: &T, i: usize, mask: &T) -> T {
+pub(crate) fn naive_pack_word<T: Word>(word: &T, i: usize, mask: &T) -> T {
     isolate_blocks(word, mask, 0) + isolate_blocks(word, mask, 1 << i)
 }
 
-pub fn count_ones<T: Word>(
-    experiment: &Vec<T>,
-    masks: [[T; 8]; 8],
-    word_length: WordLength,
-) -> Vec<T> {
-    let log_d = match word_length {
-        WordLength::U16 => 4,
-        WordLength::U32 => 5,
-        WordLength::U64 => 6,
-        WordLength::U128 => 7,
-    };
-    let mut set = experiment.clone();
-    for k in &mut set {
-        *k = naive_pack_word(&k, 0, &masks[0][1]);
-        *k = naive_pack_word(&k, 1, &masks[1][2]);
+impl WordLength {
+    #[inline(always)]
+    fn log_d(&self) -> usize {
+        match self {
+            WordLength::U16 => 4,
+            WordLength::U32 => 5,
+            WordLength::U64 => 6,
+            WordLength::U128 => 7,
+        }
+    }
+}
+
+/// Packs the words in `set` in place, and returns the number of log_d packed words at the front of `set`.
+#[inline(always)]
+fn pack_in_place<T: Word>(set: &mut [T], masks: &Masks<T>, log_d: usize) -> usize {
+    for k in set.iter_mut() {
+        *k = naive_pack_word(k, 0, masks.get(0, 1));
+        *k = naive_pack_word(k, 1, masks.get(1, 2));
     }
+    // The packed words live in set[..len]
+    let mut len = set.len();
     for i in 2..log_d {
-        for k in &mut set {
-            *k = k_prime(&k, i, &masks[i][i + 1]); // each k is now prime and (i)-packed
+        for k in &mut set[..len] {
+            *k = k_prime(k, i, masks.get(i, i + 1)); // each k is now prime and (i)-packed
         }
         let l = calculate_l(i);
         if l == calculate_l(i + 1) {
-            for k in 0..(set.len() >> 1) {
+            for k in 0..(len >> 1) {
                 set[k] = combine_words(set[k << 1], set[(k << 1) + 1], i); // each k is now (i+1)-packed
             }
-            set.truncate(set.len() >> 1) // truncate is a constant-time operation
+            if len & 1 == 1 {
+                // The last word has no partner, so it is combined with an empty word
+                set[len >> 1] = set[len - 1];
+            }
+            len = (len + 1) >> 1;
         } else {
-            for word in &mut set {
-                *word = pack_word(&word, i, &masks[l][l + 1]); // each k is now (i+1)-packed
+            for word in &mut set[..len] {
+                *word = pack_word(word, i, masks.get(l, l + 1)); // each k is now (i+1)-packed
             }
         }
     }
+    len
+}
+
+/// Returns the mask of the lowest block of a log_d packed word.
+#[inline(always)]
+fn block_mask<T: Word>(masks: &Masks<T>, log_d: usize) -> T {
+    let l = calculate_l(log_d);
+    if l < log_d {
+        *masks.get(l, log_d)
+    } else {
+        // The word is a single block, as in a u8
+        !T::zero()
+    }
+}
+
+/// Follows the j'th word of a group through `pack_in_place` and returns the index of the
+/// block holding its cardinality in the log_d packed word of that group.
+#[inline(always)]
+fn packed_block(j: usize, log_d: usize) -> usize {
+    let mut word = j;
+    let mut offset = 0;
+    for i in 2..log_d {
+        let l = calculate_l(i);
+        if l == calculate_l(i + 1) {
+            // combine_words puts the odd word of each pair on top of the even word
+            offset += (word & 1) << i;
+            word >>= 1;
+        } else if offset & (1 << l) != 0 {
+            // pack_word moves every other l-block to the upper half
+            offset += (1 << i) - (1 << l);
+        }
+    }
+    offset >> calculate_l(log_d)
+}
+
+/// Returns the combine factor of `T`, the number of words whose cardinalities are packed into a
+/// single word.
+pub fn combine_factor<T: Word>() -> usize {
+    1 << (T::LOG_D - calculate_l(T::LOG_D))
+}
+
+/// Maps the index of a word in an input of length `len` to the index of its cardinality in the
+/// output of [`count_ones_packed_into`].
+///
+/// This is the identity for every word type but `u128`, where the packing interleaves the
+/// cardinalities of each group of 8 words. A trailing group with fewer than 8 words is always left
+/// in input order.
+pub fn packed_index<T: Word>(index: usize, len: usize) -> usize {
+    let per_word = combine_factor::<T>();
+    let j = index & (per_word - 1);
+    if index - j + per_word > len {
+        return index;
+    }
+    index - j + packed_block(j, T::LOG_D)
+}
+
+#[inline(always)]
+fn count_ones_ordered<T: Word>(
+    experiment: &[T],
+    masks: &Masks<T>,
+    out: &mut [T],
+    packed_order: bool,
+) {
+    assert_eq!(
+        experiment.len(),
+        out.len(),
+        "the output buffer must be as long as the input"
+    );
+    out.copy_from_slice(experiment);
+    count_ones_in_place(out, masks, packed_order);
+}
+
+/// Replaces every word in `out` by its cardinality, using `out` as the working set for the packed
+/// words. Callers may fill `out` with any words, such as the XOR of two slices.
+#[inline(always)]
+pub(crate) fn count_ones_in_place<T: Word>(out: &mut [T], masks: &Masks<T>, packed_order: bool) {
+    let log_d = T::LOG_D;
+    let len = pack_in_place(out, masks, log_d);
 
-    // Make a vector containing the cardinalities of each element
-    let mut acc = Vec::with_capacity(experiment.len());
     let l = calculate_l(log_d);
-    // For each word in the set
-    for word in set {
-        // For each cardinality that this word contains
-        // (a log_d packed word contains 2^(log_d - l(log_d)) words)
-        for k in 0..(1 << (log_d - l)) {
-            // Push that exact cardinality to a list
-            acc.push(isolate_blocks(&word, &masks[l][log_d], k << l));
+    // A log_d packed word contains 2^(log_d - l(log_d)) cardinalities
+    let per_word = 1 << (log_d - l);
+    let block_mask = block_mask(masks, log_d);
+    let n = out.len();
+    // Unpack from the back, so that no packed word is overwritten before it has been read
+    for p in (0..len).rev() {
+        let word = out[p];
+        // The last packed word may hold fewer than per_word cardinalities
+        let group = &mut out[p * per_word..n.min((p + 1) * per_word)];
+        let complete = group.len() == per_word;
+        for (k, count) in group.iter_mut().enumerate() {
+            let block = if packed_order && complete {
+                k
+            } else {
+                packed_block(k, log_d)
+            };
+            *count = isolate_blocks(&word, &block_mask, block << l);
         }
     }
+}
+
+/// Counts the ones of every word in `experiment` and writes the cardinalities to the front of `out`,
+/// such that `out[i]` is the cardinality of `experiment[i]`.
+///
+/// `out` doubles as the working set for the packed words, so it must be exactly as long as
+/// `experiment`, and no allocation takes place. `experiment` may have any length.
+#[inline(always)]
+pub fn count_ones_into<T: Word>(experiment: &[T], masks: &Masks<T>, out: &mut [T]) {
+    count_ones_ordered(experiment, masks, out, false)
+}
+
+/// Like [`count_ones_into`], but leaves the cardinalities in the order they have in the packed words,
+/// which saves reordering them. The cardinality of `experiment[i]` is found at
+/// `out[packed_index::<T>(i, experiment.len())]`.
+pub fn count_ones_packed_into<T: Word>(experiment: &[T], masks: &Masks<T>, out: &mut [T]) {
+    count_ones_ordered(experiment, masks, out, true)
+}
+
+/// Like [`count_ones_into`], but stores the cardinalities in `out`, replacing its contents.
+///
+/// `out` only allocates if its capacity is smaller than `experiment.len()`.
+pub fn count_ones_into_vec<T: Word>(experiment: &[T], masks: &Masks<T>, out: &mut Vec<T>) {
+    out.clear();
+    out.resize(experiment.len(), T::zero());
+    count_ones_into(experiment, masks, out);
+}
+
+/// Counts the ones of every word in `experiment`.
+///
+/// `word_length` is implied by `T`, and only kept for compatibility. Panics if it does not match
+/// `T`. The masks are filled by the caller, so unlike a [`Masks`] they can be wrong.
+#[deprecated(note = "use `count_ones_into` or `count_ones_into_vec` with `T::MASKS`")]
+pub fn count_ones<T: Word>(
+    experiment: &[T],
+    masks: [[T; 8]; 8],
+    word_length: WordLength,
+) -> Vec<T> {
+    assert_eq!(
+        word_length.log_d(),
+        T::LOG_D,
+        "{:?} does not match a {}-bit word",
+        word_length,
+        T::BITS
+    );
+    let mut acc = vec![T::zero(); experiment.len()];
+    count_ones_ordered(experiment, &Masks::from_legacy(masks), &mut acc, false);
+    acc
+}
+
+/// The number of words packed at a time when the output can not double as the working set
+const PACK_BUFFER_LEN: usize = 64;
+
+/// An integer type narrower than the words, that the cardinalities can be written to.
+pub trait Cardinality: Copy {
+    /// The largest cardinality the type can hold
+    const MAX: u64;
+    fn from_u64(count: u64) -> Self;
+}
+impl Cardinality for u8 {
+    const MAX: u64 = u8::MAX as u64;
+    fn from_u64(count: u64) -> u8 {
+        count as u8
+    }
+}
+impl Cardinality for u16 {
+    const MAX: u64 = u16::MAX as u64;
+    fn from_u64(count: u64) -> u16 {
+        count as u16
+    }
+}
+impl Cardinality for u32 {
+    const MAX: u64 = u32::MAX as u64;
+    fn from_u64(count: u64) -> u32 {
+        count as u32
+    }
+}
+
+/// Packs `experiment` a buffer at a time, and calls `f` with every element of `out` and the
+/// cardinality of the word of `experiment` at the same index, in order. The cardinalities are read
+/// straight from the blocks of the packed words, and never widened to a `T`.
+///
+/// Panics if `out` is not exactly as long as `experiment`.
+fn for_each_packed_count<T: Word, O, F: FnMut(&mut O, u64)>(
+    experiment: &[T],
+    masks: &Masks<T>,
+    out: &mut [O],
+    mut f: F,
+) {
+    assert_eq!(
+        experiment.len(),
+        out.len(),
+        "the output buffer must be as long as the input"
+    );
+    let log_d = T::LOG_D;
+    let l = calculate_l(log_d);
+    let per_word = 1 << (log_d - l);
+    let block_mask = block_mask(masks, log_d);
+    let mut buffer = [T::zero(); PACK_BUFFER_LEN];
+    for (chunk, out) in experiment
+        .chunks(PACK_BUFFER_LEN)
+        .zip(out.chunks_mut(PACK_BUFFER_LEN))
+    {
+        let set = &mut buffer[..chunk.len()];
+        set.copy_from_slice(chunk);
+        let len = pack_in_place(set, masks, log_d);
+        for (word, group) in set[..len].iter().zip(out.chunks_mut(per_word)) {
+            for (k, out) in group.iter_mut().enumerate() {
+                let block = packed_block(k, log_d);
+                f(out, isolate_blocks(word, &block_mask, block << l).as_u64());
+            }
+        }
+    }
+}
+
+/// Like [`count_ones_into`], but writes the cardinalities as a narrower integer type `C`, such
+/// as `u8`, which takes up a fraction of the memory of a `T`.
+///
+/// Panics if `C` can not hold the cardinality of a word of all ones, or if `out` is not exactly
+/// as long as `experiment`.
+pub fn count_ones_narrow_into<T: Word, C: Cardinality>(
+    experiment: &[T],
+    masks: &Masks<T>,
+    out: &mut [C],
+) {
+    assert!(
+        T::BITS as u64 <= C::MAX,
+        "the cardinality of a {}-bit word does not fit in the output type",
+        T::BITS
+    );
+    for_each_packed_count(experiment, masks, out, |out, count| {
+        *out = C::from_u64(count)
+    });
+}
+
+/// Writes the running total of the cardinalities of `experiment`, starting at `offset`, to `out`,
+/// and returns the total after the last word. With `inclusive`, `out[i]` includes the cardinality
+/// of `experiment[i]`, and otherwise only those of the words before it.
+///
+/// Like [`count_ones_narrow_into`], the cardinalities are read straight from the blocks of the
+/// packed words, and never widened to a `T`.
+///
+/// Panics if `out` is not exactly as long as `experiment`.
+pub(crate) fn count_ones_scan_into<T: Word>(
+    experiment: &[T],
+    masks: &Masks<T>,
+    out: &mut [u64],
+    offset: u64,
+    inclusive: bool,
+) -> u64 {
+    let mut total = offset;
+    for_each_packed_count(experiment, masks, out, |sum, count| {
+        if inclusive {
+            total += count;
+            *sum = total;
+        } else {
+            *sum = total;
+            total += count;
+        }
+    });
+    total
+}
+
+/// Counts the ones of every word in `experiment`, returning each cardinality as a `u8`.
+pub fn count_ones_u8<T: Word>(experiment: &[T], masks: &Masks<T>) -> Vec<u8> {
+    let mut acc = vec![0; experiment.len()];
+    count_ones_narrow_into(experiment, masks, &mut acc);
+    acc
+}
+
+/// Counts the ones of every word in `experiment`, returning each cardinality as a `u16`.
+pub fn count_ones_u16<T: Word>(experiment: &[T], masks: &Masks<T>) -> Vec<u16> {
+    let mut acc = vec![0; experiment.len()];
+    count_ones_narrow_into(experiment, masks, &mut acc);
     acc
 }
 
+/// Sums the cardinalities held in the blocks of a log_d packed word.
+#[inline(always)]
+fn sum_blocks<T: Word>(word: &T, masks: &Masks<T>, log_d: usize) -> u64 {
+    let l = calculate_l(log_d);
+    let block_mask = block_mask(masks, log_d);
+    (0..1 << (log_d - l))
+        .map(|k| isolate_blocks(word, &block_mask, k << l).as_u64())
+        .sum()
+}
+
+/// A running sum of log_d packed words, which can be carried from one slice of words to the next.
+///
+/// The packed words are added together while their blocks can hold the sums, and the blocks are
+/// only widened to a `u64` when they are about to overflow, and at the end. The 8-bit blocks of a
+/// `u64` are widened every 3 packed words, those of a `u32` every 7 and of a `u16` every 15, and
+/// the 16-bit blocks of a `u128` every 511.
+pub(crate) struct PackedSum<T> {
+    acc: T,
+    acc_words: usize,
+    total: u64,
+}
+
+impl<T: Word + GetMask> PackedSum<T> {
+    // A block of 2^l bits holds up to 2^(2^l) - 1, and every packed word adds at most 2^log_d to it
+    const CAPACITY: usize = (((1u128 << (1 << calculate_l(T::LOG_D))) - 1) >> T::LOG_D) as usize;
+
+    pub(crate) fn new() -> Self {
+        PackedSum {
+            acc: T::zero(),
+            acc_words: 0,
+            total: 0,
+        }
+    }
+
+    /// Adds the ones of `experiment` to the sum.
+    pub(crate) fn add(&mut self, experiment: &[T]) {
+        let mut buffer = [T::zero(); PACK_BUFFER_LEN];
+        for chunk in experiment.chunks(PACK_BUFFER_LEN) {
+            let set = &mut buffer[..chunk.len()];
+            set.copy_from_slice(chunk);
+            self.add_in_place(set);
+        }
+    }
+
+    /// Adds the ones of the XOR of `a` and `b`, which are equally long, to the sum.
+    pub(crate) fn add_xor(&mut self, a: &[T], b: &[T]) {
+        let mut buffer = [T::zero(); PACK_BUFFER_LEN];
+        for (a, b) in a.chunks(PACK_BUFFER_LEN).zip(b.chunks(PACK_BUFFER_LEN)) {
+            let set = &mut buffer[..a.len()];
+            for (word, (a, b)) in set.iter_mut().zip(a.iter().zip(b.iter())) {
+                *word = *a ^ *b;
+            }
+            self.add_in_place(set);
+        }
+    }
+
+    /// Adds `ones`, counted without the sum, such as the sum of the cardinalities of some words.
+    pub(crate) fn add_total(&mut self, ones: u64) {
+        self.total += ones;
+    }
+
+    /// Packs the words of `set` in place, and adds the packed words to the sum.
+    #[inline(always)]
+    pub(crate) fn add_in_place(&mut self, set: &mut [T]) {
+        let masks = &T::MASKS;
+        let len = pack_in_place(set, masks, T::LOG_D);
+        for word in &set[..len] {
+            if self.acc_words == Self::CAPACITY {
+                self.total += sum_blocks(&self.acc, masks, T::LOG_D);
+                self.acc = T::zero();
+                self.acc_words = 0;
+            }
+            self.acc = self.acc + *word;
+            self.acc_words += 1;
+        }
+    }
+
+    pub(crate) fn total(&self) -> u64 {
+        self.total + sum_blocks(&self.acc, &T::MASKS, T::LOG_D)
+    }
+}
+
+/// Counts the total number of ones in `experiment`, by adding up its log_d packed words in a
+/// `PackedSum`.
+pub fn count_ones_total<T: Word + GetMask>(experiment: &[T]) -> u64 {
+    let mut sum = PackedSum::new();
+    sum.add(experiment);
+    sum.total()
+}
+
 #[test]
+#[allow(deprecated)]
 fn test_specific_128() {
     use crate::calculate_mask::GetMask;
     let input = [
@@ -162,6 +624,7 @@ fn test_specific_128() {
 }
 
 #[test]
+#[allow(deprecated)]
 fn test_random_16() {
     use crate::calculate_mask::GetMask;
     use rand::Rng;
@@ -185,6 +648,7 @@ fn test_random_16() {
     }
 }
 #[test]
+#[allow(deprecated)]
 fn test_random_32() {
     use crate::calculate_mask::GetMask;
     use rand::Rng;
@@ -208,6 +672,7 @@ fn test_random_32() {
     }
 }
 #[test]
+#[allow(deprecated)]
 fn test_random_64() {
     use crate::calculate_mask::GetMask;
     use rand::Rng;
@@ -231,6 +696,7 @@ fn test_random_64() {
     }
 }
 #[test]
+#[allow(deprecated)]
 fn test_small_sample_random_128() {
     use crate::calculate_mask::GetMask;
     let masks = u128::get_mask();
@@ -256,19 +722,15 @@ fn test_small_sample_random_128() {
     let res = count_ones(&val.clone(), masks, WordLength::U128);
     assert_eq!(res.len(), expected.len());
     for i in 0..val.len() {
-        let res_index = match i & 1 {
-            0 => (i >> 1) + ((i >> 3) << 2),
-            1 => (i >> 1) + 4 + ((i >> 3) << 2),
-            _ => panic!("i%2 gave something illegal!"),
-        };
         assert_eq!(
-            res[res_index], expected[i],
+            res[i], expected[i],
             "input: {}, left: {:b},\nright: {:b},\n i: {}",
-            val[i], res[res_index], expected[i], i
+            val[i], res[i], expected[i], i
         );
     }
 }
 #[test]
+#[allow(deprecated)]
 fn test_random_128() {
     use crate::calculate_mask::GetMask;
     use rand::Rng;
@@ -283,16 +745,219 @@ fn test_random_128() {
         let res = count_ones(&val.clone(), masks, WordLength::U128);
         assert_eq!(res.len(), expected.len());
         for i in 0..val.len() {
-            let res_index = match i & 1 {
-                0 => (i >> 1) + ((i >> 3) << 2),
-                1 => (i >> 1) + 4 + ((i >> 3) << 2),
-                _ => panic!("i%2 gave something illegal!"),
-            };
             assert_eq!(
-                res[res_index], expected[i],
+                res[i], expected[i],
                 "input: {}, left: {:b},\nright: {:b},\n i: {}",
-                val[i], res[res_index], expected[i], i
+                val[i], res[i], expected[i], i
             );
         }
     }
 }
+#[test]
+fn test_into_random_64() {
+    use crate::calculate_mask::GetMask;
+    use rand::Rng;
+    let mut rng = rand::thread_rng();
+    let val: Vec<u64> = (0..(1 << 14)).map(|_| rng.gen::<u64>()).collect();
+    let mut out = vec![0u64; val.len()];
+    count_ones_into(&val, &u64::MASKS, &mut out);
+    for i in 0..val.len() {
+        assert_eq!(out[i], val[i].count_ones() as u64, "i: {}", i);
+    }
+}
+#[test]
+fn test_into_vec_reuses_buffer() {
+    use crate::calculate_mask::GetMask;
+    use rand::Rng;
+    let mut rng = rand::thread_rng();
+    let mut out: Vec<u32> = Vec::with_capacity(1 << 10);
+    let ptr = out.as_ptr();
+    for _ in 0..10 {
+        let val: Vec<u32> = (0..(1 << 10)).map(|_| rng.gen::<u32>()).collect();
+        count_ones_into_vec(&val, &u32::MASKS, &mut out);
+        assert_eq!(out.as_ptr(), ptr);
+        let expected: Vec<u32> = val.iter().map(|x| x.count_ones()).collect();
+        assert_eq!(out, expected);
+    }
+}
+#[test]
+fn test_packed_index() {
+    for i in 0..64 {
+        assert_eq!(packed_index::<u16>(i, 64), i);
+        assert_eq!(packed_index::<u32>(i, 64), i);
+        assert_eq!(packed_index::<u64>(i, 64), i);
+        let res_index = match i & 1 {
+            0 => (i >> 1) + ((i >> 3) << 2),
+            1 => (i >> 1) + 4 + ((i >> 3) << 2),
+            _ => panic!("i%2 gave something illegal!"),
+        };
+        assert_eq!(packed_index::<u128>(i, 64), res_index);
+        assert_eq!(
+            packed_index::<u128>(i, 60),
+            if i < 56 { res_index } else { i }
+        );
+    }
+}
+#[test]
+fn test_packed_random_128() {
+    use crate::calculate_mask::GetMask;
+    use rand::Rng;
+    let mut rng = rand::thread_rng();
+    let val: Vec<u128> = (0..(1 << 14)).map(|_| rng.gen::<u128>()).collect();
+    let mut out = vec![0u128; val.len()];
+    count_ones_packed_into(&val, &u128::MASKS, &mut out);
+    for i in 0..val.len() {
+        assert_eq!(
+            out[packed_index::<u128>(i, val.len())],
+            val[i].count_ones() as u128,
+            "i: {}",
+            i
+        );
+    }
+}
+#[test]
+#[allow(deprecated)]
+fn test_every_length() {
+    use crate::calculate_mask::GetMask;
+    use rand::Rng;
+    let mut rng = rand::thread_rng();
+    for len in 0..100 {
+        let val: Vec<u16> = (0..len).map(|_| rng.gen::<u16>()).collect();
+        let expected: Vec<u16> = val.iter().map(|x| x.count_ones() as u16).collect();
+        assert_eq!(count_ones(&val, u16::get_mask(), WordLength::U16), expected);
+        let val: Vec<u32> = (0..len).map(|_| rng.gen::<u32>()).collect();
+        let expected: Vec<u32> = val.iter().map(|x| x.count_ones()).collect();
+        assert_eq!(count_ones(&val, u32::get_mask(), WordLength::U32), expected);
+        let val: Vec<u64> = (0..len).map(|_| rng.gen::<u64>()).collect();
+        let expected: Vec<u64> = val.iter().map(|x| x.count_ones() as u64).collect();
+        assert_eq!(count_ones(&val, u64::get_mask(), WordLength::U64), expected);
+        let val: Vec<u128> = (0..len).map(|_| rng.gen::<u128>()).collect();
+        let expected: Vec<u128> = val.iter().map(|x| x.count_ones() as u128).collect();
+        assert_eq!(
+            count_ones(&val, u128::get_mask(), WordLength::U128),
+            expected
+        );
+        let mut out = vec![0u128; len];
+        count_ones_packed_into(&val, &u128::MASKS, &mut out);
+        for i in 0..len {
+            assert_eq!(out[packed_index::<u128>(i, len)], expected[i]);
+        }
+    }
+}
+#[test]
+#[should_panic(expected = "U16 does not match a 64-bit word")]
+#[allow(deprecated)]
+fn test_mismatched_word_length() {
+    use crate::calculate_mask::GetMask;
+    count_ones(&[1u64, 2, 3], u64::get_mask(), WordLength::U16);
+}
+#[test]
+fn test_total() {
+    use rand::Rng;
+    let mut rng = rand::thread_rng();
+    for &len in &[0, 1, 7, 63, 64, 65, 1000, 1 << 14] {
+        let val: Vec<u16> = (0..len).map(|_| rng.gen::<u16>()).collect();
+        let expected: u64 = val.iter().map(|x| x.count_ones() as u64).sum();
+        assert_eq!(count_ones_total(&val), expected, "len: {}", len);
+        let val: Vec<u32> = (0..len).map(|_| rng.gen::<u32>()).collect();
+        let expected: u64 = val.iter().map(|x| x.count_ones() as u64).sum();
+        assert_eq!(count_ones_total(&val), expected, "len: {}", len);
+        let val: Vec<u64> = (0..len).map(|_| rng.gen::<u64>()).collect();
+        let expected: u64 = val.iter().map(|x| x.count_ones() as u64).sum();
+        assert_eq!(count_ones_total(&val), expected, "len: {}", len);
+        let val: Vec<u128> = (0..len).map(|_| rng.gen::<u128>()).collect();
+        let expected: u64 = val.iter().map(|x| x.count_ones() as u64).sum();
+        assert_eq!(count_ones_total(&val), expected, "len: {}", len);
+    }
+}
+#[test]
+fn test_total_all_ones() {
+    // Every block is filled to its capacity, so an overflow would show up here
+    assert_eq!(count_ones_total(&vec![u16::MAX; 100_000]), 1_600_000);
+    assert_eq!(count_ones_total(&vec![u32::MAX; 100_000]), 3_200_000);
+    assert_eq!(count_ones_total(&vec![u64::MAX; 100_000]), 6_400_000);
+    assert_eq!(count_ones_total(&vec![u128::MAX; 100_000]), 12_800_000);
+}
+#[test]
+fn test_narrow() {
+    use rand::Rng;
+    let mut rng = rand::thread_rng();
+    for &len in &[0, 1, 7, 63, 64, 65, 1000] {
+        let val: Vec<u16> = (0..len).map(|_| rng.gen::<u16>()).collect();
+        let expected: Vec<u8> = val.iter().map(|x| x.count_ones() as u8).collect();
+        assert_eq!(count_ones_u8(&val, &u16::MASKS), expected);
+        let val: Vec<u32> = (0..len).map(|_| rng.gen::<u32>()).collect();
+        let expected: Vec<u8> = val.iter().map(|x| x.count_ones() as u8).collect();
+        assert_eq!(count_ones_u8(&val, &u32::MASKS), expected);
+        let val: Vec<u64> = (0..len).map(|_| rng.gen::<u64>()).collect();
+        let expected: Vec<u16> = val.iter().map(|x| x.count_ones() as u16).collect();
+        assert_eq!(count_ones_u16(&val, &u64::MASKS), expected);
+        let val: Vec<u128> = (0..len).map(|_| rng.gen::<u128>()).collect();
+        let expected: Vec<u8> = val.iter().map(|x| x.count_ones() as u8).collect();
+        assert_eq!(count_ones_u8(&val, &u128::MASKS), expected);
+    }
+    assert_eq!(count_ones_u8(&[u128::MAX; 9], &u128::MASKS), vec![128; 9]);
+}
+#[test]
+fn test_random_8_and_usize() {
+    use rand::Rng;
+    let mut rng = rand::thread_rng();
+    for &len in &[0, 1, 7, 65, 1 << 14] {
+        let val: Vec<u8> = (0..len).map(|_| rng.gen::<u8>()).collect();
+        let mut res = Vec::new();
+        count_ones_into_vec(&val, &u8::MASKS, &mut res);
+        let expected: Vec<u8> = val.iter().map(|x| x.count_ones() as u8).collect();
+        assert_eq!(res, expected);
+        let total: u64 = val.iter().map(|x| x.count_ones() as u64).sum();
+        assert_eq!(count_ones_total(&val), total);
+        let val: Vec<usize> = (0..len).map(|_| rng.gen::<usize>()).collect();
+        let mut res = Vec::new();
+        count_ones_into_vec(&val, &usize::MASKS, &mut res);
+        let expected: Vec<usize> = val.iter().map(|x| x.count_ones() as usize).collect();
+        assert_eq!(res, expected);
+        let total: u64 = val.iter().map(|x| x.count_ones() as u64).sum();
+        assert_eq!(count_ones_total(&val), total);
+    }
+}
+#[test]
+fn test_random_wide() {
+    use crate::wide_word::{WideWord, U256, U512};
+    use rand::Rng;
+    let mut rng = rand::thread_rng();
+    for &len in &[0, 1, 15, 16, 17, 33, 1000] {
+        let val: Vec<U256> = (0..len).map(|_| WideWord(rng.gen())).collect();
+        let mut res = Vec::new();
+        count_ones_into_vec(&val, &U256::MASKS, &mut res);
+        for i in 0..len {
+            assert_eq!(res[i].as_u64(), val[i].count_ones() as u64, "i: {}", i);
+        }
+        let expected: Vec<u16> = val.iter().map(|x| x.count_ones() as u16).collect();
+        assert_eq!(count_ones_u16(&val, &U256::MASKS), expected);
+        let total: u64 = val.iter().map(|x| x.count_ones() as u64).sum();
+        assert_eq!(count_ones_total(&val), total);
+        let val: Vec<U512> = (0..len).map(|_| WideWord(rng.gen())).collect();
+        let expected: Vec<u16> = val.iter().map(|x| x.count_ones() as u16).collect();
+        assert_eq!(count_ones_u16(&val, &U512::MASKS), expected);
+        let total: u64 = val.iter().map(|x| x.count_ones() as u64).sum();
+        assert_eq!(count_ones_total(&val), total);
+    }
+    let ones = vec![WideWord([u64::MAX; 8]); 1000];
+    assert_eq!(count_ones_total(&ones), 512_000);
+    assert_eq!(count_ones_u16(&ones, &U512::MASKS), vec![512; 1000]);
+}
+#[test]
+fn test_packed_sum_capacity() {
+    use crate::wide_word::U256;
+    assert_eq!(PackedSum::<u8>::CAPACITY, 31);
+    assert_eq!(PackedSum::<u16>::CAPACITY, 15);
+    assert_eq!(PackedSum::<u32>::CAPACITY, 7);
+    assert_eq!(PackedSum::<u64>::CAPACITY, 3);
+    assert_eq!(PackedSum::<u128>::CAPACITY, 511);
+    assert_eq!(PackedSum::<U256>::CAPACITY, 255);
+    // Every width accumulates more than one packed word before it widens the blocks
+    let mut sum = PackedSum::<u128>::new();
+    sum.add(&[u128::MAX; 8 * 511]);
+    assert_eq!(sum.acc_words, 511);
+    assert_eq!(sum.total, 0);
+    assert_eq!(sum.total(), 128 * 8 * 511);
+}
@@ -1,4 +1,8 @@
-pub fn naive_parallel_count_ones_16(experiment: &Vec<u16>) -> Vec<u16> {
+//! The divide-and-conquer bit counting of every word on its own, for comparison with the packing
+//! of `count_ones`. Only the 16, 32, 64 and 128-bit words are covered; the other `Word` types,
+//! `u8`, `usize` and `WideWord<N>`, have no variant here.
+
+pub fn naive_parallel_count_ones_16(experiment: &[u16]) -> Vec<u16> {
     let m = [
         0b0101010101010101u16,
         0b0011001100110011u16,
@@ -9,16 +13,16 @@ pub fn naive_parallel_count_ones_16(experiment: &Vec<u16>) -> Vec<u16> {
         0b1111111111111111u16,
         0b1111111111111111u16,
     ];
-    let mut set = experiment.clone();
+    let mut set = experiment.to_vec();
     let log_d = 4;
-    for i in 0..log_d {
+    for (i, mask) in m.iter().enumerate().take(log_d) {
         for word in set.iter_mut() {
-            *word = (*word & m[i]) + ((*word >> (1 << i)) & m[i])
+            *word = (*word & mask) + ((*word >> (1 << i)) & mask)
         }
     }
     set
 }
-pub fn naive_parallel_count_ones_32(experiment: &Vec<u32>) -> Vec<u32> {
+pub fn naive_parallel_count_ones_32(experiment: &[u32]) -> Vec<u32> {
     let m = [
         0b01010101010101010101010101010101u32,
         0b00110011001100110011001100110011u32,
@@ -29,16 +33,16 @@ pub fn naive_parallel_count_ones_32(experiment: &Vec<u32>) -> Vec<u32> {
         0b11111111111111111111111111111111u32,
         0b11111111111111111111111111111111u32,
     ];
-    let mut set = experiment.clone();
-    let log_d = 6;
-    for i in 0..log_d {
+    let mut set = experiment.to_vec();
+    let log_d = 5;
+    for (i, mask) in m.iter().enumerate().take(log_d) {
         for word in set.iter_mut() {
-            *word = (*word & m[i]) + ((*word >> (1 << i)) & m[i])
+            *word = (*word & mask) + ((*word >> (1 << i)) & mask)
         }
     }
     set
 }
-pub fn naive_parallel_count_ones_64(experiment: &Vec<u64>) -> Vec<u64> {
+pub fn naive_parallel_count_ones_64(experiment: &[u64]) -> Vec<u64> {
     let m = [
         0b0101010101010101010101010101010101010101010101010101010101010101u64,
         0b0011001100110011001100110011001100110011001100110011001100110011u64,
@@ -48,16 +52,16 @@ pub fn naive_parallel_count_ones_64(experiment: &Vec<u64>) -> Vec<u64> {
         0b0000000000000000000000000000000011111111111111111111111111111111u64,
         0b1111111111111111111111111111111111111111111111111111111111111111u64,
     ];
-    let mut set = experiment.clone();
+    let mut set = experiment.to_vec();
     let log_d = 6;
-    for i in 0..log_d {
+    for (i, mask) in m.iter().enumerate().take(log_d) {
         for word in set.iter_mut() {
-            *word = (*word & m[i]) + ((*word >> (1 << i)) & m[i])
+            *word = (*word & mask) + ((*word >> (1 << i)) & mask)
         }
     }
     set
 }
-pub fn naive_parallel_count_ones_128(experiment: &Vec<u128>) -> Vec<u128> {
+pub fn naive_parallel_count_ones_128(experiment: &[u128]) -> Vec<u128> {
     let m = [0b01010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101u128,
 0b00110011001100110011001100110011001100110011001100110011001100110011001100110011001100110011001100110011001100110011001100110011u128,
 0b00001111000011110000111100001111000011110000111100001111000011110000111100001111000011110000111100001111000011110000111100001111u128,
@@ -66,15 +70,147 @@ pub fn naive_parallel_count_ones_128(experiment: &Vec<u128>) -> Vec<u128> {
 0b00000000000000000000000000000000111111111111111111111111111111110000000000000000000000000000000011111111111111111111111111111111u128,
 0b00000000000000000000000000000000000000000000000000000000000000001111111111111111111111111111111111111111111111111111111111111111u128,
     ];
-    let mut set = experiment.clone();
+    let mut set = experiment.to_vec();
     let log_d = 7;
-    for i in 0..log_d {
+    for (i, mask) in m.iter().enumerate().take(log_d) {
         for word in set.iter_mut() {
-            *word = (*word & m[i]) + ((*word >> (1 << i)) & m[i])
+            *word = (*word & mask) + ((*word >> (1 << i)) & mask)
         }
     }
     set
 }
+/// Like [`naive_parallel_count_ones_16`], but stops at the cardinalities of the bytes, and sums
+/// them with a single multiplication, which adds every byte into the top byte. The cardinalities
+/// are written to `out`, and it panics if `out` is not exactly as long as `experiment`.
+pub fn multiply_parallel_count_ones_16_into(experiment: &[u16], out: &mut [u16]) {
+    assert_eq!(
+        experiment.len(),
+        out.len(),
+        "the output buffer must be as long as the input"
+    );
+    let m = [
+        0b0101010101010101u16,
+        0b0011001100110011u16,
+        0b0000111100001111u16,
+    ];
+    let h = 0b0000000100000001u16;
+    for (word, out) in experiment.iter().zip(out.iter_mut()) {
+        let mut word = *word;
+        for (i, mask) in m.iter().enumerate() {
+            word = (word & mask) + ((word >> (1 << i)) & mask)
+        }
+        *out = word.wrapping_mul(h) >> 8
+    }
+}
+/// Like [`multiply_parallel_count_ones_16_into`], but returns the cardinalities in a new `Vec`.
+pub fn multiply_parallel_count_ones_16(experiment: &[u16]) -> Vec<u16> {
+    let mut acc = vec![0; experiment.len()];
+    multiply_parallel_count_ones_16_into(experiment, &mut acc);
+    acc
+}
+/// Like [`naive_parallel_count_ones_32`], but stops at the cardinalities of the bytes, and sums
+/// them with a single multiplication, which adds every byte into the top byte. The cardinalities
+/// are written to `out`, and it panics if `out` is not exactly as long as `experiment`.
+pub fn multiply_parallel_count_ones_32_into(experiment: &[u32], out: &mut [u32]) {
+    assert_eq!(
+        experiment.len(),
+        out.len(),
+        "the output buffer must be as long as the input"
+    );
+    let m = [
+        0b01010101010101010101010101010101u32,
+        0b00110011001100110011001100110011u32,
+        0b00001111000011110000111100001111u32,
+    ];
+    let h = 0b00000001000000010000000100000001u32;
+    for (word, out) in experiment.iter().zip(out.iter_mut()) {
+        let mut word = *word;
+        for (i, mask) in m.iter().enumerate() {
+            word = (word & mask) + ((word >> (1 << i)) & mask)
+        }
+        *out = word.wrapping_mul(h) >> 24
+    }
+}
+/// Like [`multiply_parallel_count_ones_32_into`], but returns the cardinalities in a new `Vec`.
+pub fn multiply_parallel_count_ones_32(experiment: &[u32]) -> Vec<u32> {
+    let mut acc = vec![0; experiment.len()];
+    multiply_parallel_count_ones_32_into(experiment, &mut acc);
+    acc
+}
+/// Like [`naive_parallel_count_ones_64`], but stops at the cardinalities of the bytes, and sums
+/// them with a single multiplication, which adds every byte into the top byte. The cardinalities
+/// are written to `out`, and it panics if `out` is not exactly as long as `experiment`.
+pub fn multiply_parallel_count_ones_64_into(experiment: &[u64], out: &mut [u64]) {
+    assert_eq!(
+        experiment.len(),
+        out.len(),
+        "the output buffer must be as long as the input"
+    );
+    let m = [
+        0b0101010101010101010101010101010101010101010101010101010101010101u64,
+        0b0011001100110011001100110011001100110011001100110011001100110011u64,
+        0b0000111100001111000011110000111100001111000011110000111100001111u64,
+    ];
+    let h = 0b0000000100000001000000010000000100000001000000010000000100000001u64;
+    for (word, out) in experiment.iter().zip(out.iter_mut()) {
+        let mut word = *word;
+        for (i, mask) in m.iter().enumerate() {
+            word = (word & mask) + ((word >> (1 << i)) & mask)
+        }
+        *out = word.wrapping_mul(h) >> 56
+    }
+}
+/// Like [`multiply_parallel_count_ones_64_into`], but returns the cardinalities in a new `Vec`.
+pub fn multiply_parallel_count_ones_64(experiment: &[u64]) -> Vec<u64> {
+    let mut acc = vec![0; experiment.len()];
+    multiply_parallel_count_ones_64_into(experiment, &mut acc);
+    acc
+}
+/// Like [`naive_parallel_count_ones_128`], but stops at the cardinalities of the bytes, and sums
+/// them with a single multiplication, which adds every byte into the top byte. The cardinalities
+/// are written to `out`, and it panics if `out` is not exactly as long as `experiment`.
+pub fn multiply_parallel_count_ones_128_into(experiment: &[u128], out: &mut [u128]) {
+    assert_eq!(
+        experiment.len(),
+        out.len(),
+        "the output buffer must be as long as the input"
+    );
+    let m = [0b01010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101u128,
+0b00110011001100110011001100110011001100110011001100110011001100110011001100110011001100110011001100110011001100110011001100110011u128,
+0b00001111000011110000111100001111000011110000111100001111000011110000111100001111000011110000111100001111000011110000111100001111u128,
+    ];
+    let h = 0b00000001000000010000000100000001000000010000000100000001000000010000000100000001000000010000000100000001000000010000000100000001u128;
+    for (word, out) in experiment.iter().zip(out.iter_mut()) {
+        let mut word = *word;
+        for (i, mask) in m.iter().enumerate() {
+            word = (word & mask) + ((word >> (1 << i)) & mask)
+        }
+        *out = word.wrapping_mul(h) >> 120
+    }
+}
+/// Like [`multiply_parallel_count_ones_128_into`], but returns the cardinalities in a new `Vec`.
+pub fn multiply_parallel_count_ones_128(experiment: &[u128]) -> Vec<u128> {
+    let mut acc = vec![0; experiment.len()];
+    multiply_parallel_count_ones_128_into(experiment, &mut acc);
+    acc
+}
+#[test]
+fn test_random_32() {
+    use rand::Rng;
+    for _ in 0..10 {
+        let mut rng = rand::thread_rng();
+        let mut val: Vec<u32> = Vec::with_capacity(1 << 14);
+        for _ in 0..(1 << 14) {
+            val.push(rng.gen::<u32>());
+        }
+        let expected: Vec<u32> = val.iter().map(|x| x.count_ones()).collect();
+        let res = naive_parallel_count_ones_32(&val);
+        assert_eq!(res.len(), expected.len());
+        for i in 0..val.len() {
+            assert_eq!(res[i], expected[i]);
+        }
+    }
+}
 #[test]
 fn test_random_64() {
     use rand::Rng;
@@ -109,3 +245,51 @@ fn test_random_128() {
         }
     }
 }
+#[test]
+fn test_multiply_random_16() {
+    use rand::Rng;
+    let mut rng = rand::thread_rng();
+    let mut val: Vec<u16> = (0..1 << 14).map(|_| rng.gen::<u16>()).collect();
+    val.push(u16::MAX);
+    let expected: Vec<u16> = val.iter().map(|x| x.count_ones() as u16).collect();
+    assert_eq!(multiply_parallel_count_ones_16(&val), expected);
+    let mut out = vec![0; val.len()];
+    multiply_parallel_count_ones_16_into(&val, &mut out);
+    assert_eq!(out, expected);
+}
+#[test]
+fn test_multiply_random_32() {
+    use rand::Rng;
+    let mut rng = rand::thread_rng();
+    let mut val: Vec<u32> = (0..1 << 14).map(|_| rng.gen::<u32>()).collect();
+    val.push(u32::MAX);
+    let expected: Vec<u32> = val.iter().map(|x| x.count_ones()).collect();
+    assert_eq!(multiply_parallel_count_ones_32(&val), expected);
+    let mut out = vec![0; val.len()];
+    multiply_parallel_count_ones_32_into(&val, &mut out);
+    assert_eq!(out, expected);
+}
+#[test]
+fn test_multiply_random_64() {
+    use rand::Rng;
+    let mut rng = rand::thread_rng();
+    let mut val: Vec<u64> = (0..1 << 14).map(|_| rng.gen::<u64>()).collect();
+    val.push(u64::MAX);
+    let expected: Vec<u64> = val.iter().map(|x| x.count_ones() as u64).collect();
+    assert_eq!(multiply_parallel_count_ones_64(&val), expected);
+    let mut out = vec![0; val.len()];
+    multiply_parallel_count_ones_64_into(&val, &mut out);
+    assert_eq!(out, expected);
+}
+#[test]
+fn test_multiply_random_128() {
+    use rand::Rng;
+    let mut rng = rand::thread_rng();
+    let mut val: Vec<u128> = (0..1 << 14).map(|_| rng.gen::<u128>()).collect();
+    val.push(u128::MAX);
+    let expected: Vec<u128> = val.iter().map(|x| x.count_ones() as u128).collect();
+    assert_eq!(multiply_parallel_count_ones_128(&val), expected);
+    let mut out = vec![0; val.len()];
+    multiply_parallel_count_ones_128_into(&val, &mut out);
+    assert_eq!(out, expected);
+}
@@ -0,0 +1,173 @@
+use crate::calculate_mask::GetMask;
+use crate::count_ones::count_ones_in_place;
+use crate::count_ones::PackedSum;
+use crate::count_ones::Word;
+use core::fmt;
+
+/// The reasons two slices of words have no Hamming distance.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HammingError {
+    /// The slices have different lengths
+    LengthMismatch { left: usize, right: usize },
+    /// The slices do not hold a whole number of vectors
+    PartialVector { len: usize, words_per_vector: usize },
+}
+
+impl fmt::Display for HammingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HammingError::LengthMismatch { left, right } => write!(
+                f,
+                "the slices have different lengths: {} and {}",
+                left, right
+            ),
+            HammingError::PartialVector {
+                len,
+                words_per_vector,
+            } => write!(
+                f,
+                "{} words do not make whole vectors of {} words",
+                len, words_per_vector
+            ),
+        }
+    }
+}
+
+impl std::error::Error for HammingError {}
+
+/// The Hamming distances of two slices of words.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Hamming<T> {
+    /// The number of bits that differ between every pair of words
+    pub distances: Vec<T>,
+    /// The number of bits that differ between the slices
+    pub total: u64,
+}
+
+fn check_lengths<T>(a: &[T], b: &[T]) -> Result<(), HammingError> {
+    if a.len() != b.len() {
+        return Err(HammingError::LengthMismatch {
+            left: a.len(),
+            right: b.len(),
+        });
+    }
+    Ok(())
+}
+
+/// Writes the Hamming distance of every pair of words in `a` and `b` to `out`, which must be
+/// exactly as long as them.
+///
+/// The XOR of the words is written straight into `out`, which is then packed in place like in
+/// [`count_ones_into`](crate::count_ones::count_ones_into), so no XOR buffer is allocated.
+pub fn hamming_into<T: Word + GetMask>(
+    a: &[T],
+    b: &[T],
+    out: &mut [T],
+) -> Result<(), HammingError> {
+    check_lengths(a, b)?;
+    assert_eq!(
+        a.len(),
+        out.len(),
+        "the output buffer must be as long as the input"
+    );
+    for (word, (a, b)) in out.iter_mut().zip(a.iter().zip(b.iter())) {
+        *word = *a ^ *b;
+    }
+    count_ones_in_place(out, &T::MASKS, false);
+    Ok(())
+}
+
+/// Returns the Hamming distance of every pair of words in `a` and `b`, and their sum.
+pub fn hamming<T: Word + GetMask>(a: &[T], b: &[T]) -> Result<Hamming<T>, HammingError> {
+    let mut distances = vec![T::zero(); a.len()];
+    hamming_into(a, b, &mut distances)?;
+    let total = distances.iter().map(|distance| distance.as_u64()).sum();
+    Ok(Hamming { distances, total })
+}
+
+/// Returns the number of bits that differ between `a` and `b`, by adding up the packed words of
+/// their XOR.
+pub fn hamming_total<T: Word + GetMask>(a: &[T], b: &[T]) -> Result<u64, HammingError> {
+    check_lengths(a, b)?;
+    let mut sum = PackedSum::new();
+    sum.add_xor(a, b);
+    Ok(sum.total())
+}
+
+/// Returns the Hamming distance of every pair of vectors in `a` and `b`, where every vector is
+/// `words_per_vector` consecutive words, such as a 256-bit code stored as four `u64` words.
+pub fn hamming_vectors<T: Word + GetMask>(
+    a: &[T],
+    b: &[T],
+    words_per_vector: usize,
+) -> Result<Vec<u64>, HammingError> {
+    check_lengths(a, b)?;
+    if words_per_vector == 0 || a.len() % words_per_vector != 0 {
+        return Err(HammingError::PartialVector {
+            len: a.len(),
+            words_per_vector,
+        });
+    }
+    let mut distances = vec![T::zero(); a.len()];
+    hamming_into(a, b, &mut distances)?;
+    Ok(distances
+        .chunks(words_per_vector)
+        .map(|vector| vector.iter().map(|distance| distance.as_u64()).sum())
+        .collect())
+}
+
+#[test]
+fn test_random_128() {
+    use rand::Rng;
+    let mut rng = rand::thread_rng();
+    for &len in &[0, 1, 7, 8, 9, 1000] {
+        let a: Vec<u128> = (0..len).map(|_| rng.gen()).collect();
+        let b: Vec<u128> = (0..len).map(|_| rng.gen()).collect();
+        let expected: Vec<u128> = a
+            .iter()
+            .zip(b.iter())
+            .map(|(a, b)| (a ^ b).count_ones() as u128)
+            .collect();
+        let expected_total: u64 = expected.iter().map(|&distance| distance as u64).sum();
+        let result = hamming(&a, &b).unwrap();
+        assert_eq!(result.distances, expected);
+        assert_eq!(result.total, expected_total);
+        assert_eq!(hamming_total(&a, &b), Ok(expected_total));
+        assert_eq!(hamming(&a, &a).unwrap().total, 0);
+    }
+}
+
+#[test]
+fn test_vectors() {
+    use rand::Rng;
+    let mut rng = rand::thread_rng();
+    // 100 256-bit codes
+    let a: Vec<u64> = (0..400).map(|_| rng.gen()).collect();
+    let b: Vec<u64> = (0..400).map(|_| rng.gen()).collect();
+    let expected: Vec<u64> = a
+        .chunks(4)
+        .zip(b.chunks(4))
+        .map(|(a, b)| {
+            a.iter()
+                .zip(b.iter())
+                .map(|(a, b)| (a ^ b).count_ones() as u64)
+                .sum()
+        })
+        .collect();
+    assert_eq!(hamming_vectors(&a, &b, 4), Ok(expected));
+    assert_eq!(
+        hamming_vectors(&a, &b, 3),
+        Err(HammingError::PartialVector {
+            len: 400,
+            words_per_vector: 3
+        })
+    );
+    assert_eq!(
+        hamming(&a, &b[1..]),
+        Err(HammingError::LengthMismatch {
+            left: 400,
+            right: 399
+        })
+    );
+    assert!(hamming_total(&a[1..], &b).is_err());
+}
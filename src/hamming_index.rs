@@ -0,0 +1,266 @@
+use crate::calculate_mask::GetMask;
+use crate::count_ones::count_ones_in_place;
+use crate::count_ones::Word;
+use crate::hamming::HammingError;
+use crate::multithreaded_count_ones::ThreadConfig;
+use std::collections::BinaryHeap;
+use std::thread;
+
+/// The number of words whose distances are counted at a time
+const BATCH_LEN: usize = 1 << 10;
+
+/// A code found by a search, ordered by distance and then by index.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Neighbour {
+    /// The number of bits that differ between the code and the query
+    pub distance: u64,
+    /// The position of the code in the index
+    pub index: usize,
+}
+
+/// An index of binary codes of `words_per_code` words each, stored as contiguous rows, which
+/// finds the codes closest to a query in Hamming distance.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HammingIndex<T> {
+    words_per_code: usize,
+    codes: Vec<T>,
+}
+
+/// Keeps the k smallest neighbours pushed to it.
+struct TopK {
+    k: usize,
+    heap: BinaryHeap<Neighbour>,
+}
+
+impl TopK {
+    fn new(k: usize) -> Self {
+        TopK {
+            k,
+            heap: BinaryHeap::with_capacity(k + 1),
+        }
+    }
+
+    #[inline(always)]
+    fn push(&mut self, neighbour: Neighbour) {
+        if self.heap.len() < self.k {
+            self.heap.push(neighbour);
+        } else if let Some(mut furthest) = self.heap.peek_mut() {
+            if neighbour < *furthest {
+                *furthest = neighbour;
+            }
+        }
+    }
+}
+
+impl<T: Word + GetMask> HammingIndex<T> {
+    /// Creates an empty index of codes of `words_per_code` words.
+    pub fn new(words_per_code: usize) -> Self {
+        assert!(words_per_code > 0, "a code takes at least one word");
+        HammingIndex {
+            words_per_code,
+            codes: Vec::new(),
+        }
+    }
+
+    /// Creates an index of the codes stored as consecutive rows of `words_per_code` words.
+    pub fn from_codes(words_per_code: usize, codes: Vec<T>) -> Result<Self, HammingError> {
+        if words_per_code == 0 || codes.len() % words_per_code != 0 {
+            return Err(HammingError::PartialVector {
+                len: codes.len(),
+                words_per_vector: words_per_code,
+            });
+        }
+        Ok(HammingIndex {
+            words_per_code,
+            codes,
+        })
+    }
+
+    /// Adds `code` to the end of the index.
+    pub fn push(&mut self, code: &[T]) -> Result<(), HammingError> {
+        self.check_code(code)?;
+        self.codes.extend_from_slice(code);
+        Ok(())
+    }
+
+    pub fn words_per_code(&self) -> usize {
+        self.words_per_code
+    }
+
+    /// Returns the number of codes in the index.
+    pub fn len(&self) -> usize {
+        self.codes.len() / self.words_per_code
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.codes.is_empty()
+    }
+
+    /// Returns the code at `index`.
+    pub fn code(&self, index: usize) -> &[T] {
+        &self.codes[index * self.words_per_code..(index + 1) * self.words_per_code]
+    }
+
+    fn check_code(&self, code: &[T]) -> Result<(), HammingError> {
+        if code.len() != self.words_per_code {
+            return Err(HammingError::LengthMismatch {
+                left: code.len(),
+                right: self.words_per_code,
+            });
+        }
+        Ok(())
+    }
+
+    /// Counts the distances of `query` to the codes `first_code..` in `codes`, in batches of
+    /// packed words, and pushes them to `top`.
+    fn scan(&self, query: &[T], codes: &[T], first_code: usize, top: &mut TopK) {
+        let wpc = self.words_per_code;
+        let batch_len = (BATCH_LEN / wpc).max(1) * wpc;
+        let mut batch = vec![T::zero(); batch_len.min(codes.len())];
+        for (b, rows) in codes.chunks(batch_len).enumerate() {
+            let batch = &mut batch[..rows.len()];
+            for (word, (row, query)) in batch.iter_mut().zip(rows.iter().zip(query.iter().cycle()))
+            {
+                *word = *row ^ *query;
+            }
+            count_ones_in_place(batch, &T::MASKS, false);
+            for (i, distances) in batch.chunks(wpc).enumerate() {
+                top.push(Neighbour {
+                    distance: distances.iter().map(|distance| distance.as_u64()).sum(),
+                    index: first_code + b * (batch_len / wpc) + i,
+                });
+            }
+        }
+    }
+
+    /// Returns the `k` codes closest to `query` in ascending order, by comparing it to every code.
+    pub fn search(&self, query: &[T], k: usize) -> Result<Vec<Neighbour>, HammingError> {
+        self.check_code(query)?;
+        let mut top = TopK::new(k);
+        self.scan(query, &self.codes, 0, &mut top);
+        Ok(top.heap.into_sorted_vec())
+    }
+}
+
+impl<T: Word + GetMask + Send + Sync> HammingIndex<T> {
+    /// Like [`HammingIndex::search`], but splits the codes into one chunk per thread, and merges
+    /// the `k` closest codes of every chunk.
+    pub fn search_multithreaded(
+        &self,
+        query: &[T],
+        k: usize,
+        config: &ThreadConfig,
+    ) -> Result<Vec<Neighbour>, HammingError> {
+        self.check_code(query)?;
+        if config.threads <= 1 || self.codes.len() < config.threshold {
+            return self.search(query, k);
+        }
+        let codes_per_chunk = self.len().div_ceil(config.threads).max(1);
+        let chunk_len = codes_per_chunk * self.words_per_code;
+        let mut top = TopK::new(k);
+        thread::scope(|scope| {
+            let handles: Vec<_> = self
+                .codes
+                .chunks(chunk_len)
+                .enumerate()
+                .map(|(c, codes)| {
+                    scope.spawn(move || {
+                        let mut top = TopK::new(k);
+                        self.scan(query, codes, c * codes_per_chunk, &mut top);
+                        top.heap
+                    })
+                })
+                .collect();
+            for handle in handles {
+                for neighbour in handle.join().expect("a search thread panicked") {
+                    top.push(neighbour);
+                }
+            }
+        });
+        Ok(top.heap.into_sorted_vec())
+    }
+}
+
+#[cfg(test)]
+fn naive_search(codes: &[u64], words_per_code: usize, query: &[u64], k: usize) -> Vec<Neighbour> {
+    let mut neighbours: Vec<Neighbour> = codes
+        .chunks(words_per_code)
+        .enumerate()
+        .map(|(index, code)| Neighbour {
+            distance: code
+                .iter()
+                .zip(query.iter())
+                .map(|(a, b)| (a ^ b).count_ones() as u64)
+                .sum(),
+            index,
+        })
+        .collect();
+    neighbours.sort();
+    neighbours.truncate(k);
+    neighbours
+}
+
+#[test]
+fn test_matches_naive() {
+    use rand::Rng;
+    let mut rng = rand::thread_rng();
+    let config = ThreadConfig {
+        threads: 4,
+        threshold: 0,
+    };
+    // 128-, 192- and 1024-bit codes
+    for &words_per_code in &[2, 3, 16] {
+        for &len in &[0, 1, 10, 1000] {
+            // Few bits, so that there are ties in distance
+            let codes: Vec<u64> = (0..len * words_per_code)
+                .map(|_| rng.gen::<u64>() & 0x0101)
+                .collect();
+            let index = HammingIndex::from_codes(words_per_code, codes.clone()).unwrap();
+            assert_eq!(index.len(), len);
+            let query: Vec<u64> = (0..words_per_code)
+                .map(|_| rng.gen::<u64>() & 0x0101)
+                .collect();
+            for &k in &[0, 1, 5, 2000] {
+                let expected = naive_search(&codes, words_per_code, &query, k);
+                assert_eq!(index.search(&query, k).unwrap(), expected);
+                assert_eq!(
+                    index.search_multithreaded(&query, k, &config).unwrap(),
+                    expected
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn test_wide_codes() {
+    use crate::wide_word::WideWord;
+    use crate::wide_word::U256;
+    let mut index = HammingIndex::<U256>::new(4);
+    let code = |seed: u64| [WideWord([seed, !seed, seed, 0]); 4];
+    for seed in 0..100 {
+        index.push(&code(seed)).unwrap();
+    }
+    let nearest = index.search(&code(3), 2).unwrap();
+    assert_eq!(
+        nearest[0],
+        Neighbour {
+            distance: 0,
+            index: 3
+        }
+    );
+    // 1, 2 and 7 differ from 3 in one bit, and 1 is first in the index
+    assert_eq!(
+        nearest[1],
+        Neighbour {
+            distance: 12,
+            index: 1
+        }
+    );
+    assert_eq!(
+        index.push(&code(0)[..3]),
+        Err(HammingError::LengthMismatch { left: 3, right: 4 })
+    );
+    assert!(index.search(&code(0)[..1], 1).is_err());
+    assert!(HammingIndex::<u64>::from_codes(3, vec![0; 10]).is_err());
+}
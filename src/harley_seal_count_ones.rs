@@ -0,0 +1,155 @@
+use crate::calculate_mask::GetMask;
+use crate::count_ones::count_ones_total;
+use crate::count_ones::Word;
+
+/// The number of `sixteens` words collected before their ones are counted
+const SIXTEENS_BUFFER_LEN: usize = 64;
+
+/// A carry-save adder. Adds the bits of `a`, `b` and `c` at every position, and returns the
+/// carries and the sums.
+#[inline(always)]
+fn csa<T: Word>(a: T, b: T, c: T) -> (T, T) {
+    let u = a ^ b;
+    ((a & b) | (u & c), u ^ c)
+}
+
+/// The bit-sliced partial sums of a carry-save adder tree. At every bit position, the bits of
+/// `ones`, `twos`, `fours` and `eights` are the binary digits of the number of ones added there
+/// that have not yet been carried out as sixteens.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct CsaTree<T> {
+    pub(crate) ones: T,
+    pub(crate) twos: T,
+    pub(crate) fours: T,
+    pub(crate) eights: T,
+}
+
+impl<T: Word> CsaTree<T> {
+    pub(crate) fn new() -> Self {
+        CsaTree {
+            ones: T::zero(),
+            twos: T::zero(),
+            fours: T::zero(),
+            eights: T::zero(),
+        }
+    }
+
+    /// Adds a block of 16 words to the tree, and returns the `sixteens` word whose ones each
+    /// stand for 16 ones at their position.
+    #[inline(always)]
+    pub(crate) fn add_block(&mut self, d: &[T]) -> T {
+        let (twos_a, new_ones) = csa(self.ones, d[0], d[1]);
+        let (twos_b, new_ones) = csa(new_ones, d[2], d[3]);
+        let (fours_a, new_twos) = csa(self.twos, twos_a, twos_b);
+        let (twos_a, new_ones) = csa(new_ones, d[4], d[5]);
+        let (twos_b, new_ones) = csa(new_ones, d[6], d[7]);
+        let (fours_b, new_twos) = csa(new_twos, twos_a, twos_b);
+        let (eights_a, new_fours) = csa(self.fours, fours_a, fours_b);
+        let (twos_a, new_ones) = csa(new_ones, d[8], d[9]);
+        let (twos_b, new_ones) = csa(new_ones, d[10], d[11]);
+        let (fours_a, new_twos) = csa(new_twos, twos_a, twos_b);
+        let (twos_a, new_ones) = csa(new_ones, d[12], d[13]);
+        let (twos_b, new_ones) = csa(new_ones, d[14], d[15]);
+        let (fours_b, new_twos) = csa(new_twos, twos_a, twos_b);
+        let (eights_b, new_fours) = csa(new_fours, fours_a, fours_b);
+        let (sixteens, new_eights) = csa(self.eights, eights_a, eights_b);
+        self.ones = new_ones;
+        self.twos = new_twos;
+        self.fours = new_fours;
+        self.eights = new_eights;
+        sixteens
+    }
+}
+
+/// Counts the total number of ones in `experiment` with the Harley-Seal carry-save adder tree.
+///
+/// Every block of 16 words is reduced to a single `sixteens` word, whose ones each stand for 16
+/// ones in the input, so only one in 16 words has its ones counted by [`count_ones_total`].
+pub fn harley_seal_count_ones<T: Word + GetMask>(experiment: &[T]) -> u64 {
+    let mut tree = CsaTree::new();
+    let mut sixteens = [T::zero(); SIXTEENS_BUFFER_LEN];
+    let mut sixteens_len = 0;
+    let mut sixteens_total = 0;
+
+    let mut blocks = experiment.chunks_exact(16);
+    for d in &mut blocks {
+        sixteens[sixteens_len] = tree.add_block(d);
+        sixteens_len += 1;
+        if sixteens_len == SIXTEENS_BUFFER_LEN {
+            sixteens_total += count_ones_total(&sixteens);
+            sixteens_len = 0;
+        }
+    }
+    sixteens_total += count_ones_total(&sixteens[..sixteens_len]);
+
+    16 * sixteens_total
+        + 8 * count_ones_total(&[tree.eights])
+        + 4 * count_ones_total(&[tree.fours])
+        + 2 * count_ones_total(&[tree.twos])
+        + count_ones_total(&[tree.ones])
+        + count_ones_total(blocks.remainder())
+}
+
+#[test]
+fn test_random_16() {
+    use rand::Rng;
+    for _ in 0..10 {
+        let mut rng = rand::thread_rng();
+        let mut val: Vec<u16> = Vec::with_capacity(1 << 14);
+        for _ in 0..(1 << 14) {
+            val.push(rng.gen::<u16>());
+        }
+        let expected: u64 = val.iter().map(|x| x.count_ones() as u64).sum();
+        assert_eq!(harley_seal_count_ones(&val), expected);
+    }
+}
+#[test]
+fn test_random_32() {
+    use rand::Rng;
+    for _ in 0..10 {
+        let mut rng = rand::thread_rng();
+        let mut val: Vec<u32> = Vec::with_capacity(1 << 14);
+        for _ in 0..(1 << 14) {
+            val.push(rng.gen::<u32>());
+        }
+        let expected: u64 = val.iter().map(|x| x.count_ones() as u64).sum();
+        assert_eq!(harley_seal_count_ones(&val), expected);
+    }
+}
+#[test]
+fn test_random_64() {
+    use rand::Rng;
+    for _ in 0..10 {
+        let mut rng = rand::thread_rng();
+        let mut val: Vec<u64> = Vec::with_capacity(1 << 14);
+        for _ in 0..(1 << 14) {
+            val.push(rng.gen::<u64>());
+        }
+        let expected: u64 = val.iter().map(|x| x.count_ones() as u64).sum();
+        assert_eq!(harley_seal_count_ones(&val), expected);
+    }
+}
+#[test]
+fn test_random_128() {
+    use rand::Rng;
+    for _ in 0..10 {
+        let mut rng = rand::thread_rng();
+        let mut val: Vec<u128> = Vec::with_capacity(1 << 14);
+        for _ in 0..(1 << 14) {
+            val.push(rng.gen::<u128>());
+        }
+        let expected: u64 = val.iter().map(|x| x.count_ones() as u64).sum();
+        assert_eq!(harley_seal_count_ones(&val), expected);
+    }
+}
+#[test]
+fn test_every_length() {
+    use rand::Rng;
+    let mut rng = rand::thread_rng();
+    for len in 0..300 {
+        let val: Vec<u64> = (0..len).map(|_| rng.gen::<u64>()).collect();
+        let expected: u64 = val.iter().map(|x| x.count_ones() as u64).sum();
+        assert_eq!(harley_seal_count_ones(&val), expected, "len: {}", len);
+    }
+    assert_eq!(harley_seal_count_ones(&vec![u64::MAX; 5000]), 320_000);
+}
@@ -0,0 +1,63 @@
+use crate::count_ones::Word;
+
+/// Counts the ones of `word` with Kernighan's loop, which clears the lowest one of the word until
+/// none are left, so it takes one iteration per one.
+#[inline(always)]
+pub fn kernighan_count_ones_word<T: Word>(word: T) -> u64 {
+    let mut word = word;
+    let mut count = 0;
+    while word != T::zero() {
+        word = word & (word - T::one());
+        count += 1;
+    }
+    count
+}
+
+/// Counts the ones of every word in `experiment` with Kernighan's loop, and writes the
+/// cardinalities to `out`, which must be exactly as long as `experiment`.
+pub fn kernighan_count_ones_into<T: Word>(experiment: &[T], out: &mut [T]) {
+    assert_eq!(
+        experiment.len(),
+        out.len(),
+        "the output buffer must be as long as the input"
+    );
+    for (out, word) in out.iter_mut().zip(experiment.iter()) {
+        *out = T::from_u64(kernighan_count_ones_word(*word));
+    }
+}
+
+/// Counts the total number of ones in `experiment` with Kernighan's loop.
+pub fn kernighan_count_ones_total<T: Word>(experiment: &[T]) -> u64 {
+    experiment
+        .iter()
+        .map(|word| kernighan_count_ones_word(*word))
+        .sum()
+}
+
+#[test]
+fn test_every_width() {
+    use crate::wide_word::WideWord;
+    use crate::wide_word::U512;
+    use rand::Rng;
+    let mut rng = rand::thread_rng();
+    let val: Vec<u16> = (0..1000).map(|_| rng.gen()).collect();
+    let expected: Vec<u16> = val.iter().map(|x| x.count_ones() as u16).collect();
+    let mut out = vec![0; val.len()];
+    kernighan_count_ones_into(&val, &mut out);
+    assert_eq!(out, expected);
+
+    // Sparse words, as Kernighan's loop is meant for
+    let val: Vec<u128> = (0..1000)
+        .map(|_| rng.gen::<u128>() & rng.gen::<u128>() & rng.gen::<u128>())
+        .collect();
+    let expected: Vec<u128> = val.iter().map(|x| x.count_ones() as u128).collect();
+    let mut out = vec![0; val.len()];
+    kernighan_count_ones_into(&val, &mut out);
+    assert_eq!(out, expected);
+
+    let mut val: Vec<U512> = (0..1000).map(|_| WideWord(rng.gen())).collect();
+    val.push(WideWord([u64::MAX; 8]));
+    let expected: u64 = val.iter().map(|x| x.count_ones() as u64).sum();
+    assert_eq!(kernighan_count_ones_total(&val), expected);
+    assert_eq!(kernighan_count_ones_word(WideWord([u64::MAX; 8])), 512);
+}
@@ -1,4 +1,21 @@
+pub mod adaptive_count_ones;
+pub mod autotune;
+pub mod bit_counter;
+pub mod byte_count_ones;
 pub mod calculate_mask;
 pub mod count_ones;
 pub mod divide_and_conquer_count_ones;
+pub mod hamming;
+pub mod hamming_index;
+pub mod harley_seal_count_ones;
+pub mod kernighan_count_ones;
+pub mod lookup_table_count_ones;
+pub mod multithreaded_count_ones;
 pub mod naive_count_ones;
+pub mod popcount_accumulator;
+pub mod positional_count_ones;
+pub mod prefix_sum;
+pub mod rank_select;
+pub mod set_cardinality;
+pub mod simd;
+pub mod wide_word;
@@ -0,0 +1,119 @@
+use crate::count_ones::Cardinality;
+use crate::count_ones::Word;
+
+/// Computes the cardinality of every index of a table of length N, which is a power of two.
+const fn cardinality_table<const N: usize>() -> [u8; N] {
+    let mut table = [0; N];
+    let mut i = 1;
+    while i < N {
+        // i has the ones of i / 2, and its lowest bit
+        table[i] = table[i >> 1] + (i & 1) as u8;
+        i += 1;
+    }
+    table
+}
+
+/// The cardinalities of every byte
+static TABLE_8: [u8; 1 << 8] = cardinality_table();
+/// The cardinalities of every 16-bit value
+static TABLE_16: [u8; 1 << 16] = cardinality_table();
+
+/// Counts the ones of `word` by looking up every `table_bits`-bit chunk in `table`.
+#[inline(always)]
+fn lookup<T: Word>(word: T, table: &[u8], table_bits: usize) -> u64 {
+    let chunk_mask = (1 << table_bits) - 1;
+    (0..T::BITS.div_ceil(table_bits))
+        .map(|k| table[((word >> (k * table_bits)).as_u64() & chunk_mask) as usize] as u64)
+        .sum()
+}
+
+fn lookup_into<T: Word, C: Cardinality>(
+    experiment: &[T],
+    out: &mut [C],
+    table: &[u8],
+    table_bits: usize,
+) {
+    assert!(
+        T::BITS as u64 <= C::MAX,
+        "the cardinality of a {}-bit word does not fit in the output type",
+        T::BITS
+    );
+    assert_eq!(
+        experiment.len(),
+        out.len(),
+        "the output buffer must be as long as the input"
+    );
+    for (out, word) in out.iter_mut().zip(experiment.iter()) {
+        *out = C::from_u64(lookup(*word, table, table_bits));
+    }
+}
+
+/// Counts the ones of every word in `experiment` one byte at a time, with a table of the
+/// cardinalities of every byte.
+pub fn lookup_8_count_ones_into<T: Word, C: Cardinality>(experiment: &[T], out: &mut [C]) {
+    lookup_into(experiment, out, &TABLE_8, 8);
+}
+
+/// Counts the total number of ones in `experiment` with a table of the cardinalities of every byte.
+pub fn lookup_8_count_ones_total<T: Word>(experiment: &[T]) -> u64 {
+    experiment
+        .iter()
+        .map(|word| lookup(*word, &TABLE_8, 8))
+        .sum()
+}
+
+/// Counts the ones of every word in `experiment` 16 bits at a time, with a table of the
+/// cardinalities of every 16-bit value. The table takes 64 KiB, so it only beats the 8-bit table
+/// while it stays in the cache.
+pub fn lookup_16_count_ones_into<T: Word, C: Cardinality>(experiment: &[T], out: &mut [C]) {
+    lookup_into(experiment, out, &TABLE_16, 16);
+}
+
+/// Counts the total number of ones in `experiment` with a table of the cardinalities of every
+/// 16-bit value.
+pub fn lookup_16_count_ones_total<T: Word>(experiment: &[T]) -> u64 {
+    experiment
+        .iter()
+        .map(|word| lookup(*word, &TABLE_16, 16))
+        .sum()
+}
+
+#[test]
+fn test_tables() {
+    for (i, &count) in TABLE_16.iter().enumerate() {
+        assert_eq!(count as u32, i.count_ones());
+    }
+    assert_eq!(TABLE_8[..], TABLE_16[..1 << 8]);
+}
+
+#[test]
+fn test_every_width() {
+    use crate::wide_word::WideWord;
+    use crate::wide_word::U256;
+    use rand::Rng;
+    let mut rng = rand::thread_rng();
+    let val: Vec<u8> = (0..1000).map(|_| rng.gen()).collect();
+    let expected: Vec<u16> = val.iter().map(|x| x.count_ones() as u16).collect();
+    let mut out = vec![0u16; val.len()];
+    lookup_16_count_ones_into(&val, &mut out);
+    assert_eq!(out, expected);
+    lookup_8_count_ones_into(&val, &mut out);
+    assert_eq!(out, expected);
+
+    let val: Vec<u128> = (0..1000).map(|_| rng.gen()).collect();
+    let expected: Vec<u16> = val.iter().map(|x| x.count_ones() as u16).collect();
+    lookup_16_count_ones_into(&val, &mut out);
+    assert_eq!(out, expected);
+    lookup_8_count_ones_into(&val, &mut out);
+    assert_eq!(out, expected);
+
+    let val: Vec<U256> = (0..1000).map(|_| WideWord(rng.gen())).collect();
+    let expected: Vec<u16> = val.iter().map(|x| x.count_ones() as u16).collect();
+    let expected_total: u64 = expected.iter().map(|&count| count as u64).sum();
+    lookup_16_count_ones_into(&val, &mut out);
+    assert_eq!(out, expected);
+    lookup_8_count_ones_into(&val, &mut out);
+    assert_eq!(out, expected);
+    assert_eq!(lookup_16_count_ones_total(&val), expected_total);
+    assert_eq!(lookup_8_count_ones_total(&val), expected_total);
+}
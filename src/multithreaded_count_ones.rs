@@ -0,0 +1,109 @@
+use crate::calculate_mask::Masks;
+use crate::count_ones::combine_factor;
+use crate::count_ones::count_ones_into;
+use crate::count_ones::Word;
+use std::thread;
+
+/// How [`count_ones_multithreaded_into`] splits its work.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ThreadConfig {
+    /// The number of threads to count on, including the calling thread
+    pub threads: usize,
+    /// Inputs with fewer words than this are counted on the calling thread alone
+    pub threshold: usize,
+}
+
+impl Default for ThreadConfig {
+    fn default() -> Self {
+        ThreadConfig {
+            threads: thread::available_parallelism().map_or(1, |n| n.get()),
+            threshold: 1 << 16,
+        }
+    }
+}
+
+/// Like [`count_ones_into`], but splits `experiment` into one chunk per thread.
+///
+/// Every chunk but the last holds a multiple of the combine factor of `T`, so the chunks are packed
+/// exactly as they would have been on a single thread, and `out` is identical to the output of
+/// [`count_ones_into`].
+pub fn count_ones_multithreaded_into<T: Word + Send + Sync>(
+    experiment: &[T],
+    masks: &Masks<T>,
+    out: &mut [T],
+    config: &ThreadConfig,
+) {
+    assert_eq!(
+        experiment.len(),
+        out.len(),
+        "the output buffer must be as long as the input"
+    );
+    if config.threads <= 1 || experiment.len() < config.threshold {
+        return count_ones_into(experiment, masks, out);
+    }
+    let factor = combine_factor::<T>();
+    let chunk_len = experiment
+        .len()
+        .div_ceil(config.threads)
+        .next_multiple_of(factor)
+        .max(factor);
+    thread::scope(|scope| {
+        let mut chunks = experiment.chunks(chunk_len).zip(out.chunks_mut(chunk_len));
+        // The calling thread counts the first chunk itself
+        let first = chunks.next();
+        for (chunk, out) in chunks {
+            scope.spawn(move || count_ones_into(chunk, masks, out));
+        }
+        if let Some((chunk, out)) = first {
+            count_ones_into(chunk, masks, out);
+        }
+    });
+}
+
+/// Counts the ones of every word in `experiment` on the threads given by `config`.
+pub fn count_ones_multithreaded<T: Word + Send + Sync>(
+    experiment: &[T],
+    masks: &Masks<T>,
+    config: &ThreadConfig,
+) -> Vec<T> {
+    let mut acc = vec![T::zero(); experiment.len()];
+    count_ones_multithreaded_into(experiment, masks, &mut acc, config);
+    acc
+}
+
+#[test]
+fn test_matches_single_threaded() {
+    use crate::calculate_mask::GetMask;
+    use rand::Rng;
+    let mut rng = rand::thread_rng();
+    for &len in &[0, 1, 7, 100, 1001, 1 << 14] {
+        let val: Vec<u128> = (0..len).map(|_| rng.gen::<u128>()).collect();
+        let mut expected = vec![0; len];
+        count_ones_into(&val, &u128::MASKS, &mut expected);
+        for threads in 1..=5 {
+            let config = ThreadConfig {
+                threads,
+                threshold: 0,
+            };
+            assert_eq!(
+                count_ones_multithreaded(&val, &u128::MASKS, &config),
+                expected,
+                "len: {}, threads: {}",
+                len,
+                threads
+            );
+        }
+    }
+}
+#[test]
+fn test_default_config() {
+    use crate::calculate_mask::GetMask;
+    use rand::Rng;
+    let mut rng = rand::thread_rng();
+    let val: Vec<u64> = (0..(1 << 18) + 3).map(|_| rng.gen::<u64>()).collect();
+    let expected: Vec<u64> = val.iter().map(|x| x.count_ones() as u64).collect();
+    assert_eq!(
+        count_ones_multithreaded(&val, &u64::MASKS, &ThreadConfig::default()),
+        expected
+    );
+}
@@ -64,7 +64,7 @@ fn test_random_32() {
         for _ in 0..(1 << 14) {
             val.push(rng.gen::<u32>());
         }
-        let expected: Vec<u32> = val.iter().map(|x| x.count_ones() as u32).collect();
+        let expected: Vec<u32> = val.iter().map(|x| x.count_ones()).collect();
         let res = naive_count_bits_32(&mut val);
         assert_eq!(res.len(), expected.len());
         for i in 0..res.len() {
@@ -0,0 +1,221 @@
+use crate::calculate_mask::GetMask;
+use crate::count_ones::combine_factor;
+use crate::count_ones::count_ones_into;
+use crate::count_ones::PackedSum;
+use crate::count_ones::Word;
+use std::io;
+use std::io::Read;
+
+/// The number of bytes `read_from` reads at a time
+const READ_BUFFER_LEN: usize = 1 << 12;
+
+/// Counts the ones of a stream of words that arrives in chunks of any size.
+///
+/// The words of a combine group that is cut off by the end of a chunk are carried over to the next
+/// chunk, and the running total is kept as packed words, so the counting is as efficient as if the
+/// whole stream had been passed to `count_ones` at once.
+pub struct PopcountAccumulator<T> {
+    /// The words of an incomplete combine group
+    pending: Vec<T>,
+    /// The bytes of an incomplete word, read by `read_from` or `read_counting_from`
+    pending_bytes: Vec<u8>,
+    sum: PackedSum<T>,
+}
+
+impl<T: Word + GetMask> Default for PopcountAccumulator<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Word + GetMask> PopcountAccumulator<T> {
+    pub fn new() -> Self {
+        PopcountAccumulator {
+            pending: Vec::with_capacity(combine_factor::<T>()),
+            pending_bytes: Vec::with_capacity(T::BITS / 8),
+            sum: PackedSum::new(),
+        }
+    }
+
+    /// Adds the ones of `words` to the running total.
+    pub fn push(&mut self, words: &[T]) {
+        self.push_inner(words, None);
+    }
+
+    /// Adds the ones of `words` to the running total, and appends the cardinalities of every word
+    /// in a complete combine group to `counts`, in input order. The cardinalities of the words
+    /// carried over are appended by a later call, or by [`PopcountAccumulator::finish_counting`].
+    pub fn push_counting(&mut self, words: &[T], counts: &mut Vec<T>) {
+        self.push_inner(words, Some(counts));
+    }
+
+    /// Reads `reader` to its end, and adds the ones of the bytes as little-endian words.
+    /// The bytes of a trailing incomplete word are carried over like the words of a combine group.
+    pub fn read_from<R: Read>(&mut self, reader: R) -> io::Result<()> {
+        self.read_inner(reader, None)
+    }
+
+    /// Like [`PopcountAccumulator::read_from`], but also appends cardinalities to `counts` like
+    /// [`PopcountAccumulator::push_counting`].
+    pub fn read_counting_from<R: Read>(
+        &mut self,
+        reader: R,
+        counts: &mut Vec<T>,
+    ) -> io::Result<()> {
+        self.read_inner(reader, Some(counts))
+    }
+
+    fn read_inner<R: Read>(
+        &mut self,
+        mut reader: R,
+        mut counts: Option<&mut Vec<T>>,
+    ) -> io::Result<()> {
+        let word_bytes = T::BITS / 8;
+        let mut buffer = [0u8; READ_BUFFER_LEN];
+        let mut words = Vec::with_capacity(READ_BUFFER_LEN / word_bytes + 1);
+        loop {
+            let read = match reader.read(&mut buffer) {
+                Ok(0) => return Ok(()),
+                Ok(read) => read,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            let mut bytes = &buffer[..read];
+            if !self.pending_bytes.is_empty() {
+                let missing = (word_bytes - self.pending_bytes.len()).min(bytes.len());
+                self.pending_bytes.extend_from_slice(&bytes[..missing]);
+                bytes = &bytes[missing..];
+                if self.pending_bytes.len() == word_bytes {
+                    words.push(T::from_le_bytes(&self.pending_bytes));
+                    self.pending_bytes.clear();
+                }
+            }
+            let mut chunks = bytes.chunks_exact(word_bytes);
+            words.extend((&mut chunks).map(T::from_le_bytes));
+            self.pending_bytes.extend_from_slice(chunks.remainder());
+            self.push_inner(&words, counts.as_deref_mut());
+            words.clear();
+        }
+    }
+
+    /// Returns the number of ones in the words that have been packed so far, which excludes the
+    /// words carried over.
+    pub fn total(&self) -> u64 {
+        self.sum.total()
+    }
+
+    /// Counts the words carried over, and returns the total number of ones in the stream.
+    pub fn finish(mut self) -> u64 {
+        self.flush(None);
+        self.sum.total()
+    }
+
+    /// Like [`PopcountAccumulator::finish`], but also appends the cardinalities of the words
+    /// carried over to `counts`. A trailing incomplete word from `read_from` is padded with zero
+    /// bytes, and gets a cardinality of its own.
+    pub fn finish_counting(mut self, counts: &mut Vec<T>) -> u64 {
+        self.flush(Some(counts));
+        self.sum.total()
+    }
+
+    fn flush(&mut self, counts: Option<&mut Vec<T>>) {
+        if !self.pending_bytes.is_empty() {
+            self.pending_bytes.resize(T::BITS / 8, 0);
+            self.pending.push(T::from_le_bytes(&self.pending_bytes));
+            self.pending_bytes.clear();
+        }
+        let pending = std::mem::take(&mut self.pending);
+        self.count(&pending, counts);
+    }
+
+    fn push_inner(&mut self, mut words: &[T], mut counts: Option<&mut Vec<T>>) {
+        let factor = combine_factor::<T>();
+        if !self.pending.is_empty() {
+            let missing = (factor - self.pending.len()).min(words.len());
+            self.pending.extend_from_slice(&words[..missing]);
+            words = &words[missing..];
+            if self.pending.len() < factor {
+                return;
+            }
+            let pending = std::mem::take(&mut self.pending);
+            self.count(&pending, counts.as_deref_mut());
+            self.pending = pending;
+            self.pending.clear();
+        }
+        let (complete, rest) = words.split_at(words.len() - words.len() % factor);
+        self.count(complete, counts);
+        self.pending.extend_from_slice(rest);
+    }
+
+    /// Adds the ones of `words` to the running total. When the cardinalities are appended to
+    /// `counts`, the words are packed once, and the total is summed from the cardinalities.
+    fn count(&mut self, words: &[T], counts: Option<&mut Vec<T>>) {
+        match counts {
+            Some(counts) => {
+                let start = counts.len();
+                counts.resize(start + words.len(), T::zero());
+                count_ones_into(words, &T::MASKS, &mut counts[start..]);
+                let ones = counts[start..].iter().map(|count| count.as_u64()).sum();
+                self.sum.add_total(ones);
+            }
+            None => self.sum.add(words),
+        }
+    }
+}
+
+#[test]
+fn test_chunks_of_every_size() {
+    use rand::Rng;
+    let mut rng = rand::thread_rng();
+    let val: Vec<u128> = (0..1000).map(|_| rng.gen::<u128>()).collect();
+    let expected: Vec<u128> = val.iter().map(|x| x.count_ones() as u128).collect();
+    let expected_total: u64 = val.iter().map(|x| x.count_ones() as u64).sum();
+    for chunk_len in 1..20 {
+        let mut accumulator = PopcountAccumulator::new();
+        let mut counts = Vec::new();
+        for chunk in val.chunks(chunk_len) {
+            accumulator.push_counting(chunk, &mut counts);
+            assert!(accumulator.total() <= expected_total);
+        }
+        assert_eq!(accumulator.finish_counting(&mut counts), expected_total);
+        assert_eq!(counts, expected, "chunk_len: {}", chunk_len);
+
+        let mut accumulator = PopcountAccumulator::new();
+        for chunk in val.chunks(chunk_len) {
+            accumulator.push(chunk);
+        }
+        assert_eq!(accumulator.finish(), expected_total);
+    }
+}
+
+#[test]
+fn test_read_from() {
+    use rand::Rng;
+    let mut rng = rand::thread_rng();
+    for &len in &[0, 1, 7, 8, 9, 10_000] {
+        let bytes: Vec<u8> = (0..len).map(|_| rng.gen::<u8>()).collect();
+        let expected: u64 = bytes.iter().map(|x| x.count_ones() as u64).sum();
+        let mut accumulator = PopcountAccumulator::<u64>::new();
+        // Reading in two parts cuts a word in half
+        let (first, second) = bytes.split_at(len / 2);
+        let mut counts = Vec::new();
+        accumulator.read_counting_from(first, &mut counts).unwrap();
+        accumulator.read_counting_from(second, &mut counts).unwrap();
+        assert_eq!(accumulator.finish_counting(&mut counts), expected);
+        assert_eq!(counts.len(), len.div_ceil(8));
+        let words: Vec<u64> = bytes
+            .chunks(8)
+            .map(|chunk| {
+                let mut word = [0; 8];
+                word[..chunk.len()].copy_from_slice(chunk);
+                u64::from_le_bytes(word)
+            })
+            .collect();
+        for (count, word) in counts.iter().zip(words.iter()) {
+            assert_eq!(*count, word.count_ones() as u64);
+        }
+        let mut accumulator = PopcountAccumulator::<u64>::new();
+        accumulator.read_from(&bytes[..]).unwrap();
+        assert_eq!(accumulator.finish(), expected);
+    }
+}
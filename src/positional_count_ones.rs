@@ -0,0 +1,125 @@
+use crate::count_ones::Word;
+use crate::harley_seal_count_ones::CsaTree;
+
+/// The number of bit planes of the counter the `sixteens` words are added to
+const COUNTER_PLANES: usize = 16;
+
+/// The number of `sixteens` words the counter holds before it overflows
+const COUNTER_CAPACITY: usize = (1 << COUNTER_PLANES) - 1;
+
+/// Adds `weight` to the count of every position where `plane` has a one.
+#[inline(always)]
+fn add_plane<T: Word>(counts: &mut [u64], plane: T, weight: u64) {
+    for (position, count) in counts.iter_mut().enumerate() {
+        *count += weight * ((plane >> position) & T::one()).as_u64();
+    }
+}
+
+/// Counts how many words of `experiment` have a one at every bit position, where position `i`
+/// is the bit `1 << i`. `BITS` must be `T::BITS`, and is usually inferred from the result.
+///
+/// Every block of 16 words is reduced to a `sixteens` word by the Harley-Seal carry-save adder
+/// tree, which is added to a bit-sliced counter of 16 words with a ripple carry. Only the planes
+/// of the counter and of the tree are split into bits, about once per 2^20 words.
+pub fn positional_count_ones<T: Word, const BITS: usize>(experiment: &[T]) -> [u64; BITS] {
+    assert_eq!(BITS, T::BITS, "there is a count for every bit of the word");
+    let mut counts = [0; BITS];
+    let mut tree = CsaTree::new();
+    let mut planes = [T::zero(); COUNTER_PLANES];
+    let mut planes_len = 0;
+
+    let mut blocks = experiment.chunks_exact(16);
+    for d in &mut blocks {
+        let mut carry = tree.add_block(d);
+        for plane in planes.iter_mut() {
+            if carry == T::zero() {
+                break;
+            }
+            let sum = *plane ^ carry;
+            carry = *plane & carry;
+            *plane = sum;
+        }
+        planes_len += 1;
+        if planes_len == COUNTER_CAPACITY {
+            for (i, plane) in planes.iter_mut().enumerate() {
+                add_plane(&mut counts, *plane, 16 << i);
+                *plane = T::zero();
+            }
+            planes_len = 0;
+        }
+    }
+    for (i, plane) in planes.iter().enumerate() {
+        add_plane(&mut counts, *plane, 16 << i);
+    }
+
+    add_plane(&mut counts, tree.eights, 8);
+    add_plane(&mut counts, tree.fours, 4);
+    add_plane(&mut counts, tree.twos, 2);
+    add_plane(&mut counts, tree.ones, 1);
+    for word in blocks.remainder() {
+        add_plane(&mut counts, *word, 1);
+    }
+    counts
+}
+
+/// Counts how many words of `experiment` have a one at every bit position, by testing every bit
+/// of every word.
+pub fn naive_positional_count_ones<T: Word, const BITS: usize>(experiment: &[T]) -> [u64; BITS] {
+    assert_eq!(BITS, T::BITS, "there is a count for every bit of the word");
+    let mut counts = [0; BITS];
+    for word in experiment {
+        add_plane(&mut counts, *word, 1);
+    }
+    counts
+}
+
+#[test]
+fn test_every_length() {
+    use rand::Rng;
+    let mut rng = rand::thread_rng();
+    for len in 0..300 {
+        let val: Vec<u16> = (0..len).map(|_| rng.gen()).collect();
+        let counts: [u64; 16] = positional_count_ones(&val);
+        assert_eq!(counts, naive_positional_count_ones(&val), "len: {}", len);
+        let val: Vec<u32> = (0..len).map(|_| rng.gen()).collect();
+        let counts: [u64; 32] = positional_count_ones(&val);
+        assert_eq!(counts, naive_positional_count_ones(&val), "len: {}", len);
+    }
+}
+
+#[test]
+fn test_random_64() {
+    use rand::Rng;
+    let mut rng = rand::thread_rng();
+    // The low half of the words is sparse, and bit 63 is always set
+    let val: Vec<u64> = (0..1 << 14)
+        .map(|_| (rng.gen::<u64>() & (rng.gen::<u64>() | 0xffff_ffff << 32)) | 1 << 63)
+        .collect();
+    let counts: [u64; 64] = positional_count_ones(&val);
+    assert_eq!(counts, naive_positional_count_ones(&val));
+}
+
+#[test]
+fn test_wide_words() {
+    use crate::wide_word::WideWord;
+    use crate::wide_word::U256;
+    use rand::Rng;
+    let mut rng = rand::thread_rng();
+    let val: Vec<u128> = (0..1000).map(|_| rng.gen()).collect();
+    let counts: [u64; 128] = positional_count_ones(&val);
+    assert_eq!(counts, naive_positional_count_ones(&val));
+    let val: Vec<U256> = (0..1000).map(|_| WideWord(rng.gen())).collect();
+    let counts: [u64; 256] = positional_count_ones(&val);
+    assert_eq!(counts, naive_positional_count_ones(&val));
+}
+
+#[test]
+fn test_counter_overflow() {
+    // More sixteens than the counter holds, so its planes are emptied into the counts
+    let len = 16 * COUNTER_CAPACITY + 16 * 3 + 5;
+    let val: Vec<u16> = (0..len).map(|i| 0x8001 | (i as u16 & 0x10)).collect();
+    let counts: [u64; 16] = positional_count_ones(&val);
+    assert_eq!(counts, naive_positional_count_ones(&val));
+    assert_eq!(counts[0], len as u64);
+    assert_eq!(counts[15], len as u64);
+}
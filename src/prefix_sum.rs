@@ -0,0 +1,161 @@
+use crate::calculate_mask::GetMask;
+use crate::count_ones::combine_factor;
+use crate::count_ones::count_ones_scan_into;
+use crate::count_ones::count_ones_total;
+use crate::count_ones::Word;
+use crate::multithreaded_count_ones::ThreadConfig;
+use std::thread;
+
+/// Writes the number of ones before every word of `experiment` to `out`, which must be exactly as
+/// long as it, and returns the total number of ones.
+///
+/// `out` followed by the total are the offsets of a CSR row index, where row `i` has as many
+/// entries as word `i` has ones.
+pub fn popcount_prefix_sum<T: Word + GetMask>(experiment: &[T], out: &mut [u64]) -> u64 {
+    count_ones_scan_into(experiment, &T::MASKS, out, 0, false)
+}
+
+/// Like [`popcount_prefix_sum`], but `out[i]` also includes the ones of `experiment[i]`.
+pub fn popcount_inclusive_prefix_sum<T: Word + GetMask>(experiment: &[T], out: &mut [u64]) -> u64 {
+    count_ones_scan_into(experiment, &T::MASKS, out, 0, true)
+}
+
+/// Scans `experiment` in two passes over one chunk per thread. The first pass counts the total of
+/// every chunk, from which the offset of every chunk is summed, and the second scans every chunk
+/// from its offset.
+fn scan_multithreaded<T: Word + GetMask + Send + Sync>(
+    experiment: &[T],
+    out: &mut [u64],
+    inclusive: bool,
+    config: &ThreadConfig,
+) -> u64 {
+    assert_eq!(
+        experiment.len(),
+        out.len(),
+        "the output buffer must be as long as the input"
+    );
+    if config.threads <= 1 || experiment.len() < config.threshold {
+        return count_ones_scan_into(experiment, &T::MASKS, out, 0, inclusive);
+    }
+    let factor = combine_factor::<T>();
+    let chunk_len = experiment
+        .len()
+        .div_ceil(config.threads)
+        .next_multiple_of(factor)
+        .max(factor);
+    let totals: Vec<u64> = thread::scope(|scope| {
+        let mut chunks = experiment.chunks(chunk_len);
+        // The calling thread counts the first chunk itself
+        let first = chunks.next();
+        let handles: Vec<_> = chunks
+            .map(|chunk| scope.spawn(move || count_ones_total(chunk)))
+            .collect();
+        first
+            .map(count_ones_total)
+            .into_iter()
+            .chain(
+                handles
+                    .into_iter()
+                    .map(|handle| handle.join().expect("a counting thread panicked")),
+            )
+            .collect()
+    });
+    let offsets = totals.iter().scan(0, |offset, total| {
+        let chunk_offset = *offset;
+        *offset += total;
+        Some(chunk_offset)
+    });
+    thread::scope(|scope| {
+        let mut chunks = experiment
+            .chunks(chunk_len)
+            .zip(out.chunks_mut(chunk_len))
+            .zip(offsets);
+        // The calling thread scans the first chunk itself
+        let first = chunks.next();
+        for ((chunk, out), offset) in chunks {
+            scope.spawn(move || count_ones_scan_into(chunk, &T::MASKS, out, offset, inclusive));
+        }
+        if let Some(((chunk, out), offset)) = first {
+            count_ones_scan_into(chunk, &T::MASKS, out, offset, inclusive);
+        }
+    });
+    totals.iter().sum()
+}
+
+/// Like [`popcount_prefix_sum`], but on the threads given by `config`.
+pub fn popcount_prefix_sum_multithreaded<T: Word + GetMask + Send + Sync>(
+    experiment: &[T],
+    out: &mut [u64],
+    config: &ThreadConfig,
+) -> u64 {
+    scan_multithreaded(experiment, out, false, config)
+}
+
+/// Like [`popcount_inclusive_prefix_sum`], but on the threads given by `config`.
+pub fn popcount_inclusive_prefix_sum_multithreaded<T: Word + GetMask + Send + Sync>(
+    experiment: &[T],
+    out: &mut [u64],
+    config: &ThreadConfig,
+) -> u64 {
+    scan_multithreaded(experiment, out, true, config)
+}
+
+#[cfg(test)]
+fn check_against_naive<T: Word + GetMask + Send + Sync>(val: &[T], counts: &[u64]) {
+    let config = ThreadConfig {
+        threads: 4,
+        threshold: 0,
+    };
+    let total: u64 = counts.iter().sum();
+    let exclusive: Vec<u64> = counts
+        .iter()
+        .scan(0, |sum, count| {
+            *sum += count;
+            Some(*sum - count)
+        })
+        .collect();
+    let inclusive: Vec<u64> = exclusive.iter().zip(counts).map(|(a, b)| a + b).collect();
+    let mut out = vec![u64::MAX; val.len()];
+    assert_eq!(popcount_prefix_sum(val, &mut out), total);
+    assert_eq!(out, exclusive, "len: {}", val.len());
+    assert_eq!(popcount_inclusive_prefix_sum(val, &mut out), total);
+    assert_eq!(out, inclusive, "len: {}", val.len());
+    assert_eq!(
+        popcount_prefix_sum_multithreaded(val, &mut out, &config),
+        total
+    );
+    assert_eq!(out, exclusive, "len: {}", val.len());
+    assert_eq!(
+        popcount_inclusive_prefix_sum_multithreaded(val, &mut out, &config),
+        total
+    );
+    assert_eq!(out, inclusive, "len: {}", val.len());
+}
+
+#[test]
+fn test_every_length() {
+    use rand::Rng;
+    let mut rng = rand::thread_rng();
+    for len in 0..200 {
+        let val: Vec<u16> = (0..len).map(|_| rng.gen()).collect();
+        let counts: Vec<u64> = val.iter().map(|x| x.count_ones() as u64).collect();
+        check_against_naive(&val, &counts);
+        let val: Vec<u128> = (0..len).map(|_| rng.gen()).collect();
+        let counts: Vec<u64> = val.iter().map(|x| x.count_ones() as u64).collect();
+        check_against_naive(&val, &counts);
+    }
+}
+
+#[test]
+fn test_random() {
+    use rand::Rng;
+    let mut rng = rand::thread_rng();
+    for &len in &[1000, 1 << 14, (1 << 14) + 3] {
+        let val: Vec<u32> = (0..len).map(|_| rng.gen()).collect();
+        let counts: Vec<u64> = val.iter().map(|x| x.count_ones() as u64).collect();
+        check_against_naive(&val, &counts);
+        let val: Vec<u64> = (0..len).map(|_| rng.gen()).collect();
+        let counts: Vec<u64> = val.iter().map(|x| x.count_ones() as u64).collect();
+        check_against_naive(&val, &counts);
+    }
+}
@@ -0,0 +1,392 @@
+use crate::calculate_mask::GetMask;
+use crate::count_ones::count_ones_into;
+use std::ops::Range;
+
+/// The sizes of the rank and select directories of a [`RankSelectBitVec`]. Larger blocks and
+/// sample rates take less space, but leave more words to be scanned by every query.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RankSelectConfig {
+    /// The number of words whose ones are counted by every block entry
+    pub words_per_block: usize,
+    /// The number of blocks per superblock. A superblock may hold at most 2^16 bits, so the
+    /// ranks of its blocks fit in 16 bits
+    pub blocks_per_superblock: usize,
+    /// The number of ones, and of zeros, between the select samples
+    pub select_sample_rate: usize,
+}
+
+impl Default for RankSelectConfig {
+    fn default() -> Self {
+        // 512-bit blocks and 4096-bit superblocks take 4.7% more space than the bits, and the
+        // samples another 1.6%
+        RankSelectConfig {
+            words_per_block: 8,
+            blocks_per_superblock: 8,
+            select_sample_rate: 4096,
+        }
+    }
+}
+
+/// A bitmap with directories that answer rank and select queries in near-constant time.
+///
+/// Bit `i` is bit `i % 64` of word `i / 64`. Every superblock stores the number of ones before
+/// it, and every block the number of ones between the start of its superblock and itself. The
+/// superblocks holding every `select_sample_rate`-th one and zero are sampled, which bounds the
+/// superblocks a select query searches.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RankSelectBitVec {
+    words: Vec<u64>,
+    len: usize,
+    config: RankSelectConfig,
+    /// The ones before every superblock, followed by the total number of ones
+    superblocks: Vec<u64>,
+    blocks: Vec<u16>,
+    select1_samples: Vec<usize>,
+    select0_samples: Vec<usize>,
+}
+
+/// Returns the position of the `k`-th one of `word`, counting from 0.
+#[inline(always)]
+fn select_in_word(mut word: u64, k: u64) -> usize {
+    for _ in 0..k {
+        word &= word - 1;
+    }
+    word.trailing_zeros() as usize
+}
+
+/// Returns the last index in `range` whose `before` is at most `k`, where `before` is
+/// non-decreasing and at most `k` at `range.start`.
+#[inline(always)]
+fn last_at_most<F: Fn(usize) -> u64>(range: Range<usize>, k: u64, before: F) -> usize {
+    let (mut lo, mut hi) = (range.start, range.end);
+    while hi - lo > 1 {
+        let mid = lo + (hi - lo) / 2;
+        if before(mid) <= k {
+            lo = mid;
+        } else {
+            hi = mid;
+        }
+    }
+    lo
+}
+
+impl RankSelectBitVec {
+    /// Creates a bitvector of the first `len` bits of `words`, with the default directories.
+    pub fn from_words(words: Vec<u64>, len: usize) -> Self {
+        Self::with_config(words, len, RankSelectConfig::default())
+    }
+
+    /// Creates a bitvector of `bits`, with the default directories.
+    pub fn from_bits(bits: &[bool]) -> Self {
+        let mut words = vec![0; bits.len().div_ceil(64)];
+        for (i, _) in bits.iter().enumerate().filter(|(_, bit)| **bit) {
+            words[i / 64] |= 1 << (i % 64);
+        }
+        Self::from_words(words, bits.len())
+    }
+
+    /// Creates a bitvector of the first `len` bits of `words`, and builds its directories from
+    /// the packed counts of the words.
+    pub fn with_config(mut words: Vec<u64>, len: usize, config: RankSelectConfig) -> Self {
+        assert!(
+            len <= 64 * words.len(),
+            "the words hold fewer than len bits"
+        );
+        assert!(
+            config.words_per_block > 0
+                && config.blocks_per_superblock > 0
+                && config.select_sample_rate > 0,
+            "the directories take blocks, superblocks and samples of at least one"
+        );
+        assert!(
+            64 * config.words_per_block * (config.blocks_per_superblock - 1) < 1 << 16,
+            "the ranks of the blocks must fit in 16 bits"
+        );
+        words.truncate(len.div_ceil(64));
+        if len % 64 != 0 {
+            words[len / 64] &= (1 << (len % 64)) - 1;
+        }
+
+        let mut counts = vec![0; words.len()];
+        count_ones_into(&words, &u64::MASKS, &mut counts);
+        let mut superblocks = Vec::new();
+        let mut blocks = Vec::with_capacity(words.len().div_ceil(config.words_per_block));
+        let mut ones = 0;
+        for (b, block) in counts.chunks(config.words_per_block).enumerate() {
+            if b % config.blocks_per_superblock == 0 {
+                superblocks.push(ones);
+            }
+            blocks.push((ones - superblocks[superblocks.len() - 1]) as u16);
+            ones += block.iter().sum::<u64>();
+        }
+        superblocks.push(ones);
+
+        let mut bitvec = RankSelectBitVec {
+            words,
+            len,
+            config,
+            superblocks,
+            blocks,
+            select1_samples: Vec::new(),
+            select0_samples: Vec::new(),
+        };
+        let rate = config.select_sample_rate as u64;
+        for s in 0..bitvec.superblocks.len() - 1 {
+            let (ones_end, zeros_end) = (bitvec.ones_before(s + 1), bitvec.zeros_before(s + 1));
+            while (bitvec.select1_samples.len() as u64) * rate < ones_end {
+                bitvec.select1_samples.push(s);
+            }
+            while (bitvec.select0_samples.len() as u64) * rate < zeros_end {
+                bitvec.select0_samples.push(s);
+            }
+        }
+        bitvec
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn words(&self) -> &[u64] {
+        &self.words
+    }
+
+    pub fn config(&self) -> &RankSelectConfig {
+        &self.config
+    }
+
+    /// Returns the number of bits the rank and select directories take.
+    pub fn directory_bits(&self) -> usize {
+        64 * self.superblocks.len()
+            + 16 * self.blocks.len()
+            + usize::BITS as usize * (self.select1_samples.len() + self.select0_samples.len())
+    }
+
+    /// Returns bit `i`.
+    pub fn get(&self, i: usize) -> bool {
+        assert!(i < self.len, "bit {} is out of {} bits", i, self.len);
+        self.words[i / 64] >> (i % 64) & 1 == 1
+    }
+
+    pub fn count_ones(&self) -> u64 {
+        self.superblocks[self.superblocks.len() - 1]
+    }
+
+    pub fn count_zeros(&self) -> u64 {
+        self.len as u64 - self.count_ones()
+    }
+
+    fn superblock_bits(&self) -> usize {
+        64 * self.config.words_per_block * self.config.blocks_per_superblock
+    }
+
+    fn ones_before(&self, superblock: usize) -> u64 {
+        self.superblocks[superblock]
+    }
+
+    fn zeros_before(&self, superblock: usize) -> u64 {
+        (superblock * self.superblock_bits()).min(self.len) as u64 - self.superblocks[superblock]
+    }
+
+    /// Returns the number of ones before bit `i`, which may be `len`.
+    pub fn rank1(&self, i: usize) -> u64 {
+        assert!(i <= self.len, "bit {} is out of {} bits", i, self.len);
+        if i == self.len {
+            return self.count_ones();
+        }
+        let word = i / 64;
+        let block = word / self.config.words_per_block;
+        let superblock = block / self.config.blocks_per_superblock;
+        let block_start = block * self.config.words_per_block;
+        let in_block: u64 = self.words[block_start..word]
+            .iter()
+            .map(|word| word.count_ones() as u64)
+            .sum();
+        let in_word = (self.words[word] & ((1 << (i % 64)) - 1)).count_ones() as u64;
+        self.superblocks[superblock] + self.blocks[block] as u64 + in_block + in_word
+    }
+
+    /// Returns the number of zeros before bit `i`, which may be `len`.
+    pub fn rank0(&self, i: usize) -> u64 {
+        i as u64 - self.rank1(i)
+    }
+
+    /// Returns the position of the `k`-th one, counting from 0, or `None` if there are at most
+    /// `k` ones.
+    pub fn select1(&self, k: u64) -> Option<usize> {
+        self.select(k, true)
+    }
+
+    /// Returns the position of the `k`-th zero, counting from 0, or `None` if there are at most
+    /// `k` zeros.
+    pub fn select0(&self, k: u64) -> Option<usize> {
+        self.select(k, false)
+    }
+
+    fn select(&self, k: u64, ones: bool) -> Option<usize> {
+        let (total, samples) = if ones {
+            (self.count_ones(), &self.select1_samples)
+        } else {
+            (self.count_zeros(), &self.select0_samples)
+        };
+        if k >= total {
+            return None;
+        }
+        let before = |superblock| {
+            if ones {
+                self.ones_before(superblock)
+            } else {
+                self.zeros_before(superblock)
+            }
+        };
+
+        // The next sample is in a superblock that starts at or after the k-th bit's
+        let sample = (k / self.config.select_sample_rate as u64) as usize;
+        let start = samples[sample];
+        let end = samples
+            .get(sample + 1)
+            .map_or(self.superblocks.len() - 1, |&next| next + 1);
+        let superblock = last_at_most(start..end, k, before);
+        let k = k - before(superblock);
+
+        let first_block = superblock * self.config.blocks_per_superblock;
+        let end_block = (first_block + self.config.blocks_per_superblock).min(self.blocks.len());
+        let block_bits = 64 * self.config.words_per_block as u64;
+        let block = last_at_most(first_block..end_block, k, |block| {
+            let block_ones = self.blocks[block] as u64;
+            if ones {
+                block_ones
+            } else {
+                (block - first_block) as u64 * block_bits - block_ones
+            }
+        });
+        let mut k = k - if ones {
+            self.blocks[block] as u64
+        } else {
+            (block - first_block) as u64 * block_bits - self.blocks[block] as u64
+        };
+
+        // The zeros past `len` are never reached, as there are more than k zeros before them
+        let block_start = block * self.config.words_per_block;
+        for (w, word) in self.words[block_start..].iter().enumerate() {
+            let word = if ones { *word } else { !*word };
+            let word_ones = word.count_ones() as u64;
+            if k < word_ones {
+                return Some(64 * (block_start + w) + select_in_word(word, k));
+            }
+            k -= word_ones;
+        }
+        unreachable!("the directories count more ones than the words")
+    }
+}
+
+#[cfg(test)]
+fn check_against_naive(bits: &[bool], config: RankSelectConfig) {
+    let mut words = vec![0; bits.len().div_ceil(64) + 1];
+    for (i, &bit) in bits.iter().enumerate() {
+        words[i / 64] |= (bit as u64) << (i % 64);
+    }
+    // Set bits past the end, which must be ignored
+    words[bits.len() / 64] |= !0 << (bits.len() % 64);
+    let bitvec = RankSelectBitVec::with_config(words, bits.len(), config);
+    assert_eq!(bitvec.len(), bits.len());
+    let (mut ones, mut zeros) = (0, 0);
+    for (i, &bit) in bits.iter().enumerate() {
+        assert_eq!(bitvec.get(i), bit);
+        assert_eq!(bitvec.rank1(i), ones, "rank1({}) with {:?}", i, config);
+        assert_eq!(bitvec.rank0(i), zeros, "rank0({}) with {:?}", i, config);
+        if bit {
+            assert_eq!(bitvec.select1(ones), Some(i), "select1({})", ones);
+            ones += 1;
+        } else {
+            assert_eq!(bitvec.select0(zeros), Some(i), "select0({})", zeros);
+            zeros += 1;
+        }
+    }
+    assert_eq!(bitvec.rank1(bits.len()), ones);
+    assert_eq!(bitvec.rank0(bits.len()), zeros);
+    assert_eq!(bitvec.count_ones(), ones);
+    assert_eq!(bitvec.count_zeros(), zeros);
+    assert_eq!(bitvec.select1(ones), None);
+    assert_eq!(bitvec.select0(zeros), None);
+}
+
+#[cfg(test)]
+fn test_configs() -> Vec<RankSelectConfig> {
+    let mut configs = vec![RankSelectConfig::default()];
+    for &(words_per_block, blocks_per_superblock, select_sample_rate) in
+        &[(1, 1, 1), (1, 3, 2), (2, 4, 7), (3, 2, 100), (16, 64, 64)]
+    {
+        configs.push(RankSelectConfig {
+            words_per_block,
+            blocks_per_superblock,
+            select_sample_rate,
+        });
+    }
+    configs
+}
+
+#[test]
+fn test_random() {
+    use rand::Rng;
+    let mut rng = rand::thread_rng();
+    for config in test_configs() {
+        for &len in &[0, 1, 63, 64, 65, 200, 1000, 5000] {
+            // Dense, half and sparse bitmaps
+            for &density in &[0.97, 0.5, 0.01] {
+                let bits: Vec<bool> = (0..len).map(|_| rng.gen_bool(density)).collect();
+                check_against_naive(&bits, config);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_adversarial() {
+    let len = 20_000;
+    let bitmaps: Vec<Vec<bool>> = vec![
+        vec![false; len],
+        vec![true; len],
+        (0..len).map(|i| i == len - 1).collect(),
+        (0..len).map(|i| i != 0).collect(),
+        (0..len).map(|i| i % 2 == 0).collect(),
+        // Long runs that span several superblocks
+        (0..len).map(|i| (i / 7000) % 2 == 1).collect(),
+        // A single one per superblock of the default config
+        (0..len).map(|i| i % 4096 == 4095).collect(),
+        // Whole words of ones and zeros
+        (0..len).map(|i| (i / 64) % 3 == 0).collect(),
+    ];
+    for config in test_configs() {
+        for bits in &bitmaps {
+            check_against_naive(bits, config);
+        }
+    }
+}
+
+#[test]
+fn test_directory_overhead() {
+    let bitvec = |words_per_block, blocks_per_superblock| {
+        let config = RankSelectConfig {
+            words_per_block,
+            blocks_per_superblock,
+            select_sample_rate: 4096,
+        };
+        RankSelectBitVec::with_config(vec![0x5555_5555_5555_5555; 1 << 12], 1 << 18, config)
+    };
+    let default = RankSelectBitVec::from_words(vec![0x5555_5555_5555_5555; 1 << 12], 1 << 18);
+    // Superblocks, blocks, and a sample of ones and zeros every 4096 of each
+    assert_eq!(
+        default.directory_bits(),
+        64 * 65 + 16 * 512 + usize::BITS as usize * 64
+    );
+    assert!(default.directory_bits() * 15 < default.len());
+    assert!(bitvec(1, 1).directory_bits() > bitvec(8, 8).directory_bits());
+    assert!(bitvec(8, 8).directory_bits() > bitvec(32, 32).directory_bits());
+    let bits: Vec<bool> = (0..100).map(|i| i % 3 == 0).collect();
+    assert_eq!(RankSelectBitVec::from_bits(&bits).select1(2), Some(6));
+}
@@ -0,0 +1,133 @@
+use crate::calculate_mask::GetMask;
+use crate::count_ones::PackedSum;
+use crate::count_ones::Word;
+
+/// The number of words combined at a time
+const OPERATION_BUFFER_LEN: usize = 64;
+
+/// A boolean operator on two slices of words, seen as sets of bit positions.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SetOperation {
+    /// `a & b`
+    Intersection,
+    /// `a | b`
+    Union,
+    /// `a & !b`
+    Difference,
+    /// `a ^ b`
+    SymmetricDifference,
+}
+
+/// Writes `a op b` to `set`.
+#[inline(always)]
+fn apply<T: Word>(operation: SetOperation, a: &[T], b: &[T], set: &mut [T]) {
+    let words = set.iter_mut().zip(a.iter().zip(b.iter()));
+    match operation {
+        SetOperation::Intersection => words.for_each(|(word, (a, b))| *word = *a & *b),
+        SetOperation::Union => words.for_each(|(word, (a, b))| *word = *a | *b),
+        SetOperation::Difference => words.for_each(|(word, (a, b))| *word = *a & !*b),
+        SetOperation::SymmetricDifference => words.for_each(|(word, (a, b))| *word = *a ^ *b),
+    }
+}
+
+/// Counts the ones of `a op b` for every operator in `operations`, in a single pass over `a` and
+/// `b`, which must be equally long.
+///
+/// The operators are applied to a small buffer of words at a time, which is packed in place, so
+/// no temporary slice as long as the input is built.
+pub fn set_cardinalities<T: Word + GetMask, const N: usize>(
+    a: &[T],
+    b: &[T],
+    operations: [SetOperation; N],
+) -> [u64; N] {
+    assert_eq!(a.len(), b.len(), "the slices must be equally long");
+    let mut sums: [PackedSum<T>; N] = std::array::from_fn(|_| PackedSum::new());
+    let mut buffer = [T::zero(); OPERATION_BUFFER_LEN];
+    for (a, b) in a
+        .chunks(OPERATION_BUFFER_LEN)
+        .zip(b.chunks(OPERATION_BUFFER_LEN))
+    {
+        let set = &mut buffer[..a.len()];
+        for (sum, &operation) in sums.iter_mut().zip(operations.iter()) {
+            apply(operation, a, b, set);
+            sum.add_in_place(set);
+        }
+    }
+    sums.map(|sum| sum.total())
+}
+
+/// Counts the ones of `a & b`.
+pub fn intersection_count<T: Word + GetMask>(a: &[T], b: &[T]) -> u64 {
+    let [intersection] = set_cardinalities(a, b, [SetOperation::Intersection]);
+    intersection
+}
+
+/// Counts the ones of `a | b`.
+pub fn union_count<T: Word + GetMask>(a: &[T], b: &[T]) -> u64 {
+    let [union] = set_cardinalities(a, b, [SetOperation::Union]);
+    union
+}
+
+/// Counts the ones of `a & !b`.
+pub fn difference_count<T: Word + GetMask>(a: &[T], b: &[T]) -> u64 {
+    let [difference] = set_cardinalities(a, b, [SetOperation::Difference]);
+    difference
+}
+
+/// Returns the Jaccard similarity of `a` and `b`, |a & b| / |a | b|, which is also their
+/// Tanimoto similarity. Two empty sets are equal, so their similarity is 1.
+pub fn jaccard_similarity<T: Word + GetMask>(a: &[T], b: &[T]) -> f64 {
+    let [intersection, union] =
+        set_cardinalities(a, b, [SetOperation::Intersection, SetOperation::Union]);
+    if union == 0 {
+        return 1.0;
+    }
+    intersection as f64 / union as f64
+}
+
+#[test]
+fn test_random() {
+    use rand::Rng;
+    let mut rng = rand::thread_rng();
+    for &len in &[0, 1, 7, 8, 9, 63, 64, 65, 1000] {
+        let a: Vec<u128> = (0..len).map(|_| rng.gen()).collect();
+        let b: Vec<u128> = (0..len).map(|_| rng.gen()).collect();
+        let count = |op: fn(u128, u128) -> u128| -> u64 {
+            a.iter()
+                .zip(b.iter())
+                .map(|(a, b)| op(*a, *b).count_ones() as u64)
+                .sum()
+        };
+        let expected = [
+            count(|a, b| a & b),
+            count(|a, b| a | b),
+            count(|a, b| a & !b),
+            count(|a, b| a ^ b),
+        ];
+        let operations = [
+            SetOperation::Intersection,
+            SetOperation::Union,
+            SetOperation::Difference,
+            SetOperation::SymmetricDifference,
+        ];
+        assert_eq!(set_cardinalities(&a, &b, operations), expected);
+        assert_eq!(intersection_count(&a, &b), expected[0]);
+        assert_eq!(union_count(&a, &b), expected[1]);
+        assert_eq!(difference_count(&a, &b), expected[2]);
+        // The difference from the other side
+        assert_eq!(
+            set_cardinalities(&b, &a, [SetOperation::Difference]),
+            [expected[1] - expected[0] - expected[2]]
+        );
+    }
+}
+
+#[test]
+fn test_jaccard() {
+    let a: Vec<u64> = vec![0b1111, 0];
+    let b: Vec<u64> = vec![0b0011, 0b11];
+    assert_eq!(jaccard_similarity(&a, &b), 2.0 / 6.0);
+    assert_eq!(jaccard_similarity(&a, &a), 1.0);
+    assert_eq!(jaccard_similarity::<u64>(&[], &[]), 1.0);
+    assert_eq!(jaccard_similarity(&[0u64; 4], &[0; 4]), 1.0);
+}
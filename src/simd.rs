@@ -0,0 +1,467 @@
+use crate::calculate_mask::GetMask;
+use crate::count_ones;
+
+/// The instruction sets [`count_ones_into_with`] can run the packing pipeline on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backend {
+    /// One `u64` at a time
+    Scalar,
+    /// Two `u64` lanes in a 128-bit vector
+    Sse2,
+    /// Four `u64` lanes in a 256-bit vector
+    Avx2,
+}
+
+impl Backend {
+    /// Returns the widest backend the running CPU supports.
+    pub fn detect() -> Backend {
+        #[cfg(target_arch = "x86_64")]
+        {
+            if is_x86_feature_detected!("avx2") {
+                Backend::Avx2
+            } else {
+                Backend::Sse2
+            }
+        }
+        #[cfg(not(target_arch = "x86_64"))]
+        {
+            Backend::Scalar
+        }
+    }
+
+    pub fn is_supported(self) -> bool {
+        match self {
+            Backend::Scalar => true,
+            #[cfg(target_arch = "x86_64")]
+            Backend::Sse2 => true,
+            #[cfg(target_arch = "x86_64")]
+            Backend::Avx2 => is_x86_feature_detected!("avx2"),
+            #[cfg(not(target_arch = "x86_64"))]
+            _ => false,
+        }
+    }
+}
+
+/// Counts the ones of every word in `experiment` into `out`, like [`count_ones::count_ones_into`],
+/// but runs the packing pipeline on as many lanes at once as the CPU supports.
+pub fn count_ones_into(experiment: &[u64], out: &mut [u64]) {
+    count_ones_into_with(Backend::detect(), experiment, out)
+}
+
+/// Like [`count_ones_into`], but on the given backend, which gives the same cardinalities as every
+/// other backend. Panics if the CPU does not support `backend`.
+pub fn count_ones_into_with(backend: Backend, experiment: &[u64], out: &mut [u64]) {
+    assert!(
+        backend.is_supported(),
+        "{:?} is not supported by this CPU",
+        backend
+    );
+    assert_eq!(
+        experiment.len(),
+        out.len(),
+        "the output buffer must be as long as the input"
+    );
+    match backend {
+        Backend::Scalar => count_ones::count_ones_into(experiment, &u64::MASKS, out),
+        #[cfg(target_arch = "x86_64")]
+        Backend::Sse2 => x86::count_ones_sse2(experiment, out),
+        // Safety: is_supported checked that the CPU has AVX2
+        #[cfg(target_arch = "x86_64")]
+        Backend::Avx2 => unsafe { x86::count_ones_avx2(experiment, out) },
+        #[cfg(not(target_arch = "x86_64"))]
+        _ => unreachable!(),
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod x86 {
+    use crate::calculate_mask::Masks;
+    use crate::calculate_mask::MASKS_U64;
+    use crate::calculate_mask::MASK_TABLE_LEN;
+    use crate::count_ones::{self, Word};
+    use core::fmt;
+    use std::arch::x86_64::*;
+    use std::convert::TryInto;
+    use std::mem::transmute;
+    use std::ops::Add;
+    use std::ops::BitAnd;
+    use std::ops::BitOr;
+    use std::ops::BitXor;
+    use std::ops::Not;
+    use std::ops::Shl;
+    use std::ops::Shr;
+    use std::ops::Sub;
+
+    /// The number of vectors packed at a time
+    const VECTOR_BUFFER_LEN: usize = 64;
+
+    /// A vector of `u64` lanes. Every lane is a word of its own, so the packing pipeline counts
+    /// the ones of `LANES` words at once.
+    trait Lanes: Word {
+        const LANES: usize;
+        fn load(words: &[u64]) -> Self;
+        fn store(self, words: &mut [u64]);
+    }
+
+    macro_rules! lanes {
+        ($name:ident, $vector:ty, $lanes:expr, $masks:ident) => {
+            impl fmt::Debug for $name {
+                fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                    f.debug_tuple(stringify!($name))
+                        .field(&self.to_array())
+                        .finish()
+                }
+            }
+            impl fmt::Binary for $name {
+                fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                    f.debug_list()
+                        .entries(self.to_array().iter().map(|lane| format!("{:b}", lane)))
+                        .finish()
+                }
+            }
+            impl PartialEq for $name {
+                fn eq(&self, other: &Self) -> bool {
+                    self.to_array() == other.to_array()
+                }
+            }
+            impl Eq for $name {}
+            impl $name {
+                #[inline(always)]
+                fn to_array(self) -> [u64; $lanes] {
+                    unsafe { transmute::<$vector, [u64; $lanes]>(self.0) }
+                }
+            }
+            impl Word for $name {
+                // The lanes are words of their own, so a vector is as long as a u64
+                const LOG_D: usize = 6;
+                #[inline(always)]
+                fn zero() -> Self {
+                    $name(unsafe { transmute::<[u64; $lanes], $vector>([0; $lanes]) })
+                }
+                #[inline(always)]
+                fn one() -> Self {
+                    $name(unsafe { transmute::<[u64; $lanes], $vector>([1; $lanes]) })
+                }
+                fn as_u64(self) -> u64 {
+                    self.to_array()[0]
+                }
+                /// Broadcasts `value` to every lane, like `one`
+                #[inline(always)]
+                fn from_u64(value: u64) -> Self {
+                    $name(unsafe { transmute::<[u64; $lanes], $vector>([value; $lanes]) })
+                }
+                fn from_le_bytes(bytes: &[u8]) -> Self {
+                    assert_eq!(bytes.len(), 8 * $lanes, "a word takes BITS / 8 bytes");
+                    let mut lanes = [0u64; $lanes];
+                    for (lane, bytes) in lanes.iter_mut().zip(bytes.chunks_exact(8)) {
+                        *lane = u64::from_le_bytes(bytes.try_into().unwrap());
+                    }
+                    Self::load(&lanes)
+                }
+            }
+
+            /// Broadcasts the masks of a u64 to every lane.
+            const $masks: Masks<$name> = {
+                let mut table =
+                    [[$name(unsafe { transmute::<[u64; $lanes], $vector>([0; $lanes]) }); MASK_TABLE_LEN]; MASK_TABLE_LEN];
+                let mut i = 0;
+                while i < MASK_TABLE_LEN {
+                    let mut j = 0;
+                    while j < MASK_TABLE_LEN {
+                        table[i][j] = $name(unsafe { transmute::<[u64; $lanes], $vector>([*MASKS_U64.get(i, j); $lanes]) });
+                        j += 1;
+                    }
+                    i += 1;
+                }
+                Masks::from_table(table)
+            };
+        };
+    }
+
+    /// Two u64 lanes. SSE2 is part of every x86_64 CPU.
+    #[derive(Clone, Copy)]
+    struct U64x2(__m128i);
+    lanes!(U64x2, __m128i, 2, MASKS_X2);
+
+    impl Lanes for U64x2 {
+        const LANES: usize = 2;
+        #[inline(always)]
+        fn load(words: &[u64]) -> Self {
+            assert!(words.len() >= 2);
+            U64x2(unsafe { _mm_loadu_si128(words.as_ptr() as *const __m128i) })
+        }
+        #[inline(always)]
+        fn store(self, words: &mut [u64]) {
+            assert!(words.len() >= 2);
+            unsafe { _mm_storeu_si128(words.as_mut_ptr() as *mut __m128i, self.0) }
+        }
+    }
+    impl Add for U64x2 {
+        type Output = Self;
+        #[inline(always)]
+        fn add(self, other: Self) -> Self {
+            U64x2(unsafe { _mm_add_epi64(self.0, other.0) })
+        }
+    }
+    impl Sub for U64x2 {
+        type Output = Self;
+        #[inline(always)]
+        fn sub(self, other: Self) -> Self {
+            U64x2(unsafe { _mm_sub_epi64(self.0, other.0) })
+        }
+    }
+    impl BitAnd for U64x2 {
+        type Output = Self;
+        #[inline(always)]
+        fn bitand(self, other: Self) -> Self {
+            U64x2(unsafe { _mm_and_si128(self.0, other.0) })
+        }
+    }
+    impl BitOr for U64x2 {
+        type Output = Self;
+        #[inline(always)]
+        fn bitor(self, other: Self) -> Self {
+            U64x2(unsafe { _mm_or_si128(self.0, other.0) })
+        }
+    }
+    impl BitXor for U64x2 {
+        type Output = Self;
+        #[inline(always)]
+        fn bitxor(self, other: Self) -> Self {
+            U64x2(unsafe { _mm_xor_si128(self.0, other.0) })
+        }
+    }
+    impl Not for U64x2 {
+        type Output = Self;
+        #[inline(always)]
+        fn not(self) -> Self {
+            U64x2(unsafe { _mm_xor_si128(self.0, _mm_set1_epi64x(-1)) })
+        }
+    }
+    impl Shl<usize> for U64x2 {
+        type Output = Self;
+        #[inline(always)]
+        fn shl(self, shift: usize) -> Self {
+            U64x2(unsafe { _mm_sll_epi64(self.0, _mm_cvtsi64_si128(shift as i64)) })
+        }
+    }
+    impl Shr<usize> for U64x2 {
+        type Output = Self;
+        #[inline(always)]
+        fn shr(self, shift: usize) -> Self {
+            U64x2(unsafe { _mm_srl_epi64(self.0, _mm_cvtsi64_si128(shift as i64)) })
+        }
+    }
+
+    /// The AVX2 instructions of the U64x4 ops. They enable the target feature themselves, so they
+    /// compile to AVX2 even where the compiler declines to inline them into `count_ones_avx2`.
+    ///
+    /// # Safety
+    /// The CPU must support AVX2.
+    mod avx2 {
+        use std::arch::x86_64::*;
+
+        #[inline]
+        #[target_feature(enable = "avx2")]
+        pub(super) unsafe fn load(words: &[u64]) -> __m256i {
+            assert!(words.len() >= 4);
+            _mm256_loadu_si256(words.as_ptr() as *const __m256i)
+        }
+        #[inline]
+        #[target_feature(enable = "avx2")]
+        pub(super) unsafe fn store(vector: __m256i, words: &mut [u64]) {
+            assert!(words.len() >= 4);
+            _mm256_storeu_si256(words.as_mut_ptr() as *mut __m256i, vector)
+        }
+        #[inline]
+        #[target_feature(enable = "avx2")]
+        pub(super) unsafe fn add(a: __m256i, b: __m256i) -> __m256i {
+            _mm256_add_epi64(a, b)
+        }
+        #[inline]
+        #[target_feature(enable = "avx2")]
+        pub(super) unsafe fn sub(a: __m256i, b: __m256i) -> __m256i {
+            _mm256_sub_epi64(a, b)
+        }
+        #[inline]
+        #[target_feature(enable = "avx2")]
+        pub(super) unsafe fn and(a: __m256i, b: __m256i) -> __m256i {
+            _mm256_and_si256(a, b)
+        }
+        #[inline]
+        #[target_feature(enable = "avx2")]
+        pub(super) unsafe fn or(a: __m256i, b: __m256i) -> __m256i {
+            _mm256_or_si256(a, b)
+        }
+        #[inline]
+        #[target_feature(enable = "avx2")]
+        pub(super) unsafe fn xor(a: __m256i, b: __m256i) -> __m256i {
+            _mm256_xor_si256(a, b)
+        }
+        #[inline]
+        #[target_feature(enable = "avx2")]
+        pub(super) unsafe fn not(a: __m256i) -> __m256i {
+            _mm256_xor_si256(a, _mm256_set1_epi64x(-1))
+        }
+        #[inline]
+        #[target_feature(enable = "avx2")]
+        pub(super) unsafe fn shl(a: __m256i, shift: usize) -> __m256i {
+            _mm256_sll_epi64(a, _mm_cvtsi64_si128(shift as i64))
+        }
+        #[inline]
+        #[target_feature(enable = "avx2")]
+        pub(super) unsafe fn shr(a: __m256i, shift: usize) -> __m256i {
+            _mm256_srl_epi64(a, _mm_cvtsi64_si128(shift as i64))
+        }
+    }
+
+    /// Four u64 lanes.
+    ///
+    /// Safety: a U64x4 is only ever created by `count_ones_avx2`, whose caller has checked that the
+    /// CPU supports AVX2, so every op may call the functions of `avx2`.
+    #[derive(Clone, Copy)]
+    struct U64x4(__m256i);
+    lanes!(U64x4, __m256i, 4, MASKS_X4);
+
+    impl Lanes for U64x4 {
+        const LANES: usize = 4;
+        #[inline(always)]
+        fn load(words: &[u64]) -> Self {
+            U64x4(unsafe { avx2::load(words) })
+        }
+        #[inline(always)]
+        fn store(self, words: &mut [u64]) {
+            unsafe { avx2::store(self.0, words) }
+        }
+    }
+    impl Add for U64x4 {
+        type Output = Self;
+        #[inline(always)]
+        fn add(self, other: Self) -> Self {
+            U64x4(unsafe { avx2::add(self.0, other.0) })
+        }
+    }
+    impl Sub for U64x4 {
+        type Output = Self;
+        #[inline(always)]
+        fn sub(self, other: Self) -> Self {
+            U64x4(unsafe { avx2::sub(self.0, other.0) })
+        }
+    }
+    impl BitAnd for U64x4 {
+        type Output = Self;
+        #[inline(always)]
+        fn bitand(self, other: Self) -> Self {
+            U64x4(unsafe { avx2::and(self.0, other.0) })
+        }
+    }
+    impl BitOr for U64x4 {
+        type Output = Self;
+        #[inline(always)]
+        fn bitor(self, other: Self) -> Self {
+            U64x4(unsafe { avx2::or(self.0, other.0) })
+        }
+    }
+    impl BitXor for U64x4 {
+        type Output = Self;
+        #[inline(always)]
+        fn bitxor(self, other: Self) -> Self {
+            U64x4(unsafe { avx2::xor(self.0, other.0) })
+        }
+    }
+    impl Not for U64x4 {
+        type Output = Self;
+        #[inline(always)]
+        fn not(self) -> Self {
+            U64x4(unsafe { avx2::not(self.0) })
+        }
+    }
+    impl Shl<usize> for U64x4 {
+        type Output = Self;
+        #[inline(always)]
+        fn shl(self, shift: usize) -> Self {
+            U64x4(unsafe { avx2::shl(self.0, shift) })
+        }
+    }
+    impl Shr<usize> for U64x4 {
+        type Output = Self;
+        #[inline(always)]
+        fn shr(self, shift: usize) -> Self {
+            U64x4(unsafe { avx2::shr(self.0, shift) })
+        }
+    }
+
+    /// Loads the words into vectors, packs them, and stores the cardinalities of every lane back
+    /// where its word came from. The words that do not fill a vector are counted one at a time.
+    #[inline(always)]
+    fn count_ones_lanes<V: Lanes>(experiment: &[u64], out: &mut [u64], masks: &Masks<V>) {
+        let mut vectors = [V::zero(); VECTOR_BUFFER_LEN];
+        let mut counts = [V::zero(); VECTOR_BUFFER_LEN];
+        let vector_words = experiment.len() - experiment.len() % V::LANES;
+        for (chunk, out) in experiment[..vector_words]
+            .chunks(V::LANES * VECTOR_BUFFER_LEN)
+            .zip(out[..vector_words].chunks_mut(V::LANES * VECTOR_BUFFER_LEN))
+        {
+            let n = chunk.len() / V::LANES;
+            for (vector, words) in vectors.iter_mut().zip(chunk.chunks_exact(V::LANES)) {
+                *vector = V::load(words);
+            }
+            count_ones::count_ones_into(&vectors[..n], masks, &mut counts[..n]);
+            for (count, words) in counts[..n].iter().zip(out.chunks_exact_mut(V::LANES)) {
+                count.store(words);
+            }
+        }
+        count_ones::count_ones_into(
+            &experiment[vector_words..],
+            &MASKS_U64,
+            &mut out[vector_words..],
+        );
+    }
+
+    pub(super) fn count_ones_sse2(experiment: &[u64], out: &mut [u64]) {
+        count_ones_lanes(experiment, out, &MASKS_X2)
+    }
+
+    /// # Safety
+    /// The CPU must support AVX2.
+    #[target_feature(enable = "avx2")]
+    pub(super) unsafe fn count_ones_avx2(experiment: &[u64], out: &mut [u64]) {
+        count_ones_lanes(experiment, out, &MASKS_X4)
+    }
+
+    #[test]
+    fn test_sub_matches_u64() {
+        use rand::Rng;
+        let mut rng = rand::thread_rng();
+        let a: [u64; 2] = rng.gen();
+        let b: [u64; 2] = rng.gen();
+        let difference = U64x2::load(&a) - U64x2::load(&b) - U64x2::one();
+        let expected = [
+            a[0].wrapping_sub(b[0]).wrapping_sub(1),
+            a[1].wrapping_sub(b[1]).wrapping_sub(1),
+        ];
+        assert_eq!(difference.to_array(), expected);
+    }
+}
+
+#[test]
+fn test_backends_match_scalar() {
+    use rand::Rng;
+    let mut rng = rand::thread_rng();
+    for &len in &[0, 1, 2, 3, 5, 8, 63, 127, 128, 129, 1000, 1 << 14] {
+        let val: Vec<u64> = (0..len).map(|_| rng.gen::<u64>()).collect();
+        let mut expected = vec![0; len];
+        count_ones::count_ones_into(&val, &u64::MASKS, &mut expected);
+        for &backend in &[Backend::Scalar, Backend::Sse2, Backend::Avx2] {
+            if !backend.is_supported() {
+                continue;
+            }
+            let mut out = vec![0; len];
+            count_ones_into_with(backend, &val, &mut out);
+            assert_eq!(out, expected, "backend: {:?}, len: {}", backend, len);
+        }
+        let mut out = vec![0; len];
+        count_ones_into(&val, &mut out);
+        assert_eq!(out, expected);
+    }
+}
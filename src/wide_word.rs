@@ -0,0 +1,283 @@
+use crate::calculate_mask::get_basic_masks_u64;
+use crate::calculate_mask::GetMask;
+use crate::calculate_mask::Masks;
+use crate::calculate_mask::MASK_TABLE_LEN;
+use crate::count_ones::Word;
+use core::fmt;
+use std::convert::TryInto;
+use std::ops::Add;
+use std::ops::BitAnd;
+use std::ops::BitOr;
+use std::ops::BitXor;
+use std::ops::Not;
+use std::ops::Shl;
+use std::ops::Shr;
+use std::ops::Sub;
+
+/// A word of 64 * N bits, stored as N little-endian `u64` limbs.
+///
+/// N must be a power of two, and at most 8, so the widest word has 512 bits.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct WideWord<const N: usize>(pub [u64; N]);
+
+pub type U256 = WideWord<4>;
+pub type U512 = WideWord<8>;
+
+impl<const N: usize> WideWord<N> {
+    pub fn count_ones(&self) -> u32 {
+        self.0.iter().map(|limb| limb.count_ones()).sum()
+    }
+}
+
+impl<const N: usize> Add for WideWord<N> {
+    type Output = Self;
+    #[inline(always)]
+    fn add(self, other: Self) -> Self {
+        let mut limbs = [0; N];
+        let mut carry = false;
+        for (limb, (a, b)) in limbs.iter_mut().zip(self.0.iter().zip(other.0.iter())) {
+            let (sum, carry_a) = a.overflowing_add(*b);
+            let (sum, carry_b) = sum.overflowing_add(carry as u64);
+            *limb = sum;
+            carry = carry_a || carry_b;
+        }
+        WideWord(limbs)
+    }
+}
+
+impl<const N: usize> Sub for WideWord<N> {
+    type Output = Self;
+    #[inline(always)]
+    fn sub(self, other: Self) -> Self {
+        let mut limbs = [0; N];
+        let mut borrow = false;
+        for (limb, (a, b)) in limbs.iter_mut().zip(self.0.iter().zip(other.0.iter())) {
+            let (difference, borrow_a) = a.overflowing_sub(*b);
+            let (difference, borrow_b) = difference.overflowing_sub(borrow as u64);
+            *limb = difference;
+            borrow = borrow_a || borrow_b;
+        }
+        WideWord(limbs)
+    }
+}
+
+impl<const N: usize> BitAnd for WideWord<N> {
+    type Output = Self;
+    #[inline(always)]
+    fn bitand(self, other: Self) -> Self {
+        let mut limbs = self.0;
+        for (limb, b) in limbs.iter_mut().zip(other.0.iter()) {
+            *limb &= b;
+        }
+        WideWord(limbs)
+    }
+}
+
+impl<const N: usize> BitOr for WideWord<N> {
+    type Output = Self;
+    #[inline(always)]
+    fn bitor(self, other: Self) -> Self {
+        let mut limbs = self.0;
+        for (limb, b) in limbs.iter_mut().zip(other.0.iter()) {
+            *limb |= b;
+        }
+        WideWord(limbs)
+    }
+}
+
+impl<const N: usize> BitXor for WideWord<N> {
+    type Output = Self;
+    #[inline(always)]
+    fn bitxor(self, other: Self) -> Self {
+        let mut limbs = self.0;
+        for (limb, b) in limbs.iter_mut().zip(other.0.iter()) {
+            *limb ^= b;
+        }
+        WideWord(limbs)
+    }
+}
+
+impl<const N: usize> Not for WideWord<N> {
+    type Output = Self;
+    #[inline(always)]
+    fn not(self) -> Self {
+        let mut limbs = self.0;
+        for limb in limbs.iter_mut() {
+            *limb = !*limb;
+        }
+        WideWord(limbs)
+    }
+}
+
+impl<const N: usize> Shl<usize> for WideWord<N> {
+    type Output = Self;
+    #[inline(always)]
+    fn shl(self, shift: usize) -> Self {
+        let (limb_shift, bit_shift) = (shift / 64, shift % 64);
+        let mut limbs = [0; N];
+        for (i, limb) in limbs.iter_mut().enumerate().skip(limb_shift) {
+            *limb = self.0[i - limb_shift] << bit_shift;
+            if bit_shift > 0 && i > limb_shift {
+                *limb |= self.0[i - limb_shift - 1] >> (64 - bit_shift);
+            }
+        }
+        WideWord(limbs)
+    }
+}
+
+impl<const N: usize> Shr<usize> for WideWord<N> {
+    type Output = Self;
+    #[inline(always)]
+    fn shr(self, shift: usize) -> Self {
+        let (limb_shift, bit_shift) = (shift / 64, shift % 64);
+        let mut limbs = [0; N];
+        for (i, limb) in limbs
+            .iter_mut()
+            .take(N.saturating_sub(limb_shift))
+            .enumerate()
+        {
+            *limb = self.0[i + limb_shift] >> bit_shift;
+            if bit_shift > 0 && i + limb_shift + 1 < N {
+                *limb |= self.0[i + limb_shift + 1] << (64 - bit_shift);
+            }
+        }
+        WideWord(limbs)
+    }
+}
+
+impl<const N: usize> fmt::Binary for WideWord<N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut digits = String::new();
+        for limb in self.0.iter().rev() {
+            if !digits.is_empty() {
+                digits += &format!("{:064b}", limb);
+            } else if *limb != 0 {
+                digits += &format!("{:b}", limb);
+            }
+        }
+        if digits.is_empty() {
+            digits.push('0');
+        }
+        f.pad_integral(true, "0b", &digits)
+    }
+}
+
+impl<const N: usize> Word for WideWord<N> {
+    const LOG_D: usize = 6 + N.trailing_zeros() as usize;
+    fn zero() -> Self {
+        WideWord([0; N])
+    }
+    fn one() -> Self {
+        let mut limbs = [0; N];
+        limbs[0] = 1;
+        WideWord(limbs)
+    }
+    fn as_u64(self) -> u64 {
+        self.0[0]
+    }
+    fn from_u64(value: u64) -> Self {
+        let mut limbs = [0; N];
+        limbs[0] = value;
+        WideWord(limbs)
+    }
+    fn from_le_bytes(bytes: &[u8]) -> Self {
+        assert_eq!(bytes.len(), 8 * N, "a word takes BITS / 8 bytes");
+        let mut limbs = [0; N];
+        for (limb, bytes) in limbs.iter_mut().zip(bytes.chunks_exact(8)) {
+            *limb = u64::from_le_bytes(bytes.try_into().unwrap());
+        }
+        WideWord(limbs)
+    }
+}
+
+/// Returns the i'th basic mask of a WideWord<N>.
+const fn basic_mask_wide<const N: usize>(i: usize) -> [u64; N] {
+    let basic_masks = get_basic_masks_u64();
+    let mut limbs = [0; N];
+    let mut j = 0;
+    while j < N {
+        limbs[j] = if i < 6 {
+            basic_masks[i]
+        } else if (j >> (i - 6)) & 1 == 0 {
+            // Blocks of 2^i bits span 2^(i - 6) limbs, and the mask covers every other block
+            u64::MAX
+        } else {
+            0
+        };
+        j += 1;
+    }
+    limbs
+}
+
+/// Computes the mask table of a WideWord<N> in a const context.
+const fn mask_table_wide<const N: usize>() -> [[WideWord<N>; MASK_TABLE_LEN]; MASK_TABLE_LEN] {
+    assert!(
+        N.is_power_of_two() && 6 + (N.trailing_zeros() as usize) < MASK_TABLE_LEN,
+        "N must be a power of two, and at most 8"
+    );
+    let mut array = [[WideWord([0; N]); MASK_TABLE_LEN]; MASK_TABLE_LEN];
+    let mut i = 0;
+    while i < MASK_TABLE_LEN {
+        let mut j = i + 1;
+        while j < MASK_TABLE_LEN {
+            let mut limbs = [u64::MAX; N];
+            let mut k = i;
+            while k < j {
+                let basic_mask = basic_mask_wide::<N>(k);
+                let mut limb = 0;
+                while limb < N {
+                    limbs[limb] &= basic_mask[limb];
+                    limb += 1;
+                }
+                k += 1;
+            }
+            array[i][j] = WideWord(limbs);
+            j += 1;
+        }
+        i += 1;
+    }
+    array
+}
+
+impl<const N: usize> GetMask for WideWord<N> {
+    const MASKS: Masks<Self> = Masks::from_table(mask_table_wide::<N>());
+}
+
+#[test]
+fn test_ops_match_u128() {
+    use rand::Rng;
+    let mut rng = rand::thread_rng();
+    let to_wide = |x: u128| WideWord::<2>([x as u64, (x >> 64) as u64]);
+    for _ in 0..1000 {
+        let a = rng.gen::<u128>();
+        let b = rng.gen::<u128>();
+        let shift = rng.gen_range(0..128);
+        assert_eq!(to_wide(a) + to_wide(b), to_wide(a.wrapping_add(b)));
+        assert_eq!(to_wide(a) - to_wide(b), to_wide(a.wrapping_sub(b)));
+        assert_eq!(to_wide(a) & to_wide(b), to_wide(a & b));
+        assert_eq!(to_wide(a) | to_wide(b), to_wide(a | b));
+        assert_eq!(to_wide(a) ^ to_wide(b), to_wide(a ^ b));
+        assert_eq!(!to_wide(a), to_wide(!a));
+        assert_eq!(to_wide(a) << shift, to_wide(a << shift));
+        assert_eq!(to_wide(a) >> shift, to_wide(a >> shift));
+        assert_eq!(format!("{:b}", to_wide(a)), format!("{:b}", a));
+    }
+}
+
+#[test]
+fn test_masks_match_u128() {
+    let masks = WideWord::<2>::MASKS;
+    let expected = u128::MASKS;
+    for i in 0..MASK_TABLE_LEN {
+        for j in 0..MASK_TABLE_LEN {
+            let mask = *expected.get(i, j);
+            assert_eq!(
+                *masks.get(i, j),
+                WideWord([mask as u64, (mask >> 64) as u64]),
+                "i: {}, j: {}",
+                i,
+                j
+            );
+        }
+    }
+}